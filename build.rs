@@ -0,0 +1,37 @@
+use std::env;
+use std::path::PathBuf;
+
+const MODELS: [(&str, &str); 2] = [
+    ("EVILEYE_EMBEDDED_DETECTION_MODEL", "text-detection.rten"),
+    (
+        "EVILEYE_EMBEDDED_RECOGNITION_MODEL",
+        "text-recognition.rten",
+    ),
+];
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=EVILEYE_EMBED_MODEL_DIR");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_MODELS").is_none() {
+        return;
+    }
+
+    // Models are embedded from EVILEYE_EMBED_MODEL_DIR, or from the crate
+    // root.
+    let dir = env::var_os("EVILEYE_EMBED_MODEL_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()));
+
+    for (var, name) in MODELS {
+        let path = dir.join(name);
+        if !path.is_file() {
+            panic!(
+                "the embedded-models feature needs {}; install the models with \
+                 `evileye models fetch --dir <DIR>` and set EVILEYE_EMBED_MODEL_DIR=<DIR>",
+                path.display()
+            );
+        }
+        let path = path.canonicalize().unwrap();
+        println!("cargo:rerun-if-changed={}", path.display());
+        println!("cargo:rustc-env={}={}", var, path.display());
+    }
+}
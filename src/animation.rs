@@ -0,0 +1,214 @@
+//! Animated GIF, APNG and WebP images. Screen recordings hold the same
+//! picture for many frames, so by default a frame is only OCR'd once it looks
+//! different from the last one that was.
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat, ImageResult, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// How much a channel may differ before a pixel counts as changed. Keeps
+/// dithering and compression noise from making every frame look new.
+const PIXEL_TOLERANCE: u8 = 24;
+
+/// Share of pixels that must change before another frame is OCR'd. Small
+/// enough for a few characters of text on a full-screen recording.
+const MIN_CHANGED_SHARE: f64 = 0.0005;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// Stop after OCRing this many frames of one animation.
+    pub max_frames: usize,
+    /// OCR every frame, even ones that look like the last one OCR'd.
+    pub all_frames: bool,
+}
+
+/// How much of an animation was scanned.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct FrameStats {
+    /// Frames decoded, which is all of them unless `truncated`.
+    pub total: usize,
+    /// Frames OCR'd.
+    pub scanned: usize,
+    /// `--max-frames` was reached before the end of the animation.
+    pub truncated: bool,
+}
+
+/// An animation, kept encoded so that its frames can be decoded one at a
+/// time.
+pub struct Animation {
+    format: ImageFormat,
+    bytes: Vec<u8>,
+    dimensions: (u32, u32),
+}
+
+pub struct Frame {
+    /// Position in the animation, counting frames that were not OCR'd.
+    pub index: usize,
+    /// When the frame is first shown, from the start of the animation.
+    pub timestamp_ms: u64,
+    pub pixels: RgbImage,
+}
+
+/// Whether `bytes`, of the given format, may hold more than one frame.
+/// Every GIF qualifies, since counting its frames means decoding them.
+pub fn is_animated(format: ImageFormat, bytes: &[u8]) -> bool {
+    match format {
+        ImageFormat::Gif => true,
+        ImageFormat::Png => PngDecoder::new(Cursor::new(bytes))
+            .and_then(|decoder| decoder.is_apng())
+            .unwrap_or(false),
+        ImageFormat::WebP => {
+            WebPDecoder::new(Cursor::new(bytes)).is_ok_and(|decoder| decoder.has_animation())
+        }
+        _ => false,
+    }
+}
+
+impl Animation {
+    /// Reads the header of an image that `is_animated`.
+    pub fn new(format: ImageFormat, bytes: Vec<u8>) -> ImageResult<Animation> {
+        let reader = Cursor::new(bytes.as_slice());
+        let dimensions = match format {
+            ImageFormat::Gif => GifDecoder::new(reader)?.dimensions(),
+            ImageFormat::Png => PngDecoder::new(reader)?.dimensions(),
+            _ => WebPDecoder::new(reader)?.dimensions(),
+        };
+        Ok(Animation {
+            format,
+            bytes,
+            dimensions,
+        })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    /// The frames worth OCRing, decoded as they are needed.
+    pub fn frames(&self, settings: Settings) -> ImageResult<Frames<'_>> {
+        Ok(Frames {
+            inner: self.decode()?,
+            settings,
+            elapsed_ms: 0.0,
+            last: None,
+            pending: None,
+            stats: FrameStats::default(),
+            done: false,
+        })
+    }
+
+    /// Decodes just the frame at `index`, for rendering a thumbnail.
+    pub fn frame(&self, index: usize) -> ImageResult<Option<RgbImage>> {
+        self.decode()?
+            .nth(index)
+            .transpose()
+            .map(|frame| frame.map(|frame| DynamicImage::from(frame.into_buffer()).into_rgb8()))
+    }
+
+    fn decode(&self) -> ImageResult<image::Frames<'_>> {
+        let reader = Cursor::new(self.bytes.as_slice());
+        Ok(match self.format {
+            ImageFormat::Gif => GifDecoder::new(reader)?.into_frames(),
+            ImageFormat::Png => PngDecoder::new(reader)?.apng()?.into_frames(),
+            _ => WebPDecoder::new(reader)?.into_frames(),
+        })
+    }
+}
+
+/// Yields every frame that differs enough from the last one yielded, plus
+/// the final state of the animation if it changed only a little since.
+pub struct Frames<'a> {
+    inner: image::Frames<'a>,
+    settings: Settings,
+    elapsed_ms: f64,
+    /// Pixels of the last frame yielded.
+    last: Option<RgbImage>,
+    /// The latest frame that differs slightly from `last`.
+    pending: Option<Frame>,
+    stats: FrameStats,
+    done: bool,
+}
+
+impl Frames<'_> {
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    fn yield_frame(&mut self, frame: Frame) -> Option<ImageResult<Frame>> {
+        self.pending = None;
+        self.last = Some(frame.pixels.clone());
+        self.stats.scanned += 1;
+        Some(Ok(frame))
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = ImageResult<Frame>;
+
+    fn next(&mut self) -> Option<ImageResult<Frame>> {
+        while !self.done {
+            let Some(next) = self.inner.next() else {
+                self.done = true;
+                let max_frames = self.settings.max_frames;
+                return self
+                    .pending
+                    .take()
+                    .filter(|_| self.stats.scanned < max_frames)
+                    .and_then(|frame| self.yield_frame(frame));
+            };
+            if self.stats.scanned == self.settings.max_frames {
+                self.stats.truncated = true;
+                self.done = true;
+                return None;
+            }
+            let frame = match next {
+                Ok(frame) => frame,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let frame = Frame {
+                index: self.stats.total,
+                timestamp_ms: self.elapsed_ms.round() as u64,
+                pixels: DynamicImage::from(frame.into_buffer()).into_rgb8(),
+            };
+            self.stats.total += 1;
+            self.elapsed_ms += numer as f64 / denom.max(1) as f64;
+
+            let changed = match &self.last {
+                Some(last) => changed_share(last, &frame.pixels),
+                None => 1.0,
+            };
+            if self.settings.all_frames || changed >= MIN_CHANGED_SHARE {
+                return self.yield_frame(frame);
+            }
+            if changed > 0.0 {
+                self.pending = Some(frame);
+            }
+        }
+        None
+    }
+}
+
+/// The share of pixels that differ noticeably between two frames.
+fn changed_share(a: &RgbImage, b: &RgbImage) -> f64 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    let changed = a
+        .pixels()
+        .zip(b.pixels())
+        .filter(|(a, b)| {
+            a.0.iter()
+                .zip(b.0.iter())
+                .any(|(a, b)| a.abs_diff(*b) > PIXEL_TOLERANCE)
+        })
+        .count();
+    changed as f64 / (a.width() as f64 * a.height() as f64).max(1.0)
+}
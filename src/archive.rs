@@ -0,0 +1,486 @@
+//! Zip and tar archives (plain or compressed with gzip, bzip2 or xz), as
+//! found in backups, chat exports and zipped bug reports. Their members are
+//! looked at like files, and anything worth scanning inside is reported as
+//! `outer.zip!/inner.tar!/shot.png`.
+//!
+//! An archive can decompress to far more than its own size, and can contain
+//! itself, so every top-level archive gets a budget of `Limits`, shared with
+//! the archives and documents nested in it. PDFs and Office documents on disk
+//! get a budget of their own.
+
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use lzma_rust2::XzReader;
+use std::fmt;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Local file header signature of a zip archive.
+pub const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// The most any one member may decompress to, however much is left of the
+/// budget, since it is held in memory.
+pub const MAX_MEMBER_SIZE: u64 = 256 << 20;
+
+/// Where a POSIX tar archive carries its magic.
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+/// Memory the xz decoder may use for its dictionary, in KiB. Far more than
+/// `xz -9` needs.
+const XZ_MEMORY_LIMIT_KB: u32 = 256 << 10;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarBz2,
+    TarXz,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// How many archives deep to look; 0 leaves archives alone.
+    pub max_depth: usize,
+    /// Bytes that may be decompressed from one top-level archive or
+    /// document, including everything nested in it.
+    pub max_size: u64,
+    /// Members, pages and embedded images that may be looked at in one
+    /// top-level archive or document.
+    pub max_entries: u64,
+}
+
+/// What is left of the `Limits` of one top-level archive or document.
+pub struct Budget {
+    limits: Limits,
+    size: u64,
+    entries: u64,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The archive is corrupt, encrypted or uses an unsupported feature.
+    Unreadable(String),
+    /// The budget ran out.
+    Limit(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Unreadable(err.to_string())
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(err: ZipError) -> Error {
+        Error::Unreadable(err.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unreadable(message) => write!(f, "unreadable archive: {}", message),
+            Error::Limit(message) => f.write_str(message),
+        }
+    }
+}
+
+/// A regular file inside an archive.
+pub struct Member<'a> {
+    /// Path within the archive, without a leading `./` or `/`.
+    pub name: String,
+    /// Uncompressed size as recorded in the archive.
+    pub size: u64,
+    pub reader: &'a mut dyn Read,
+}
+
+/// Looks at one member, with what is left of the budget.
+pub type Visit<'v> = dyn FnMut(Member, &mut Budget) -> Result<bool, Error> + 'v;
+
+/// Recognizes compressed tars only by extension, since a bare `.gz` is far
+/// more likely to be a log file.
+pub fn from_name(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    [
+        (".zip", Format::Zip),
+        (".tar", Format::Tar),
+        (".tar.gz", Format::TarGz),
+        (".tgz", Format::TarGz),
+        (".tar.bz2", Format::TarBz2),
+        (".tbz2", Format::TarBz2),
+        (".tbz", Format::TarBz2),
+        (".tar.xz", Format::TarXz),
+        (".txz", Format::TarXz),
+    ]
+    .into_iter()
+    .find(|(ext, _)| name.ends_with(ext))
+    .map(|(_, format)| format)
+}
+
+/// Recognizes zip and POSIX tar archives by their magic bytes.
+pub fn from_header(header: &[u8]) -> Option<Format> {
+    if header.starts_with(ZIP_MAGIC) {
+        Some(Format::Zip)
+    } else if header
+        .get(TAR_MAGIC_OFFSET..)
+        .is_some_and(|magic| magic.starts_with(TAR_MAGIC))
+    {
+        Some(Format::Tar)
+    } else {
+        None
+    }
+}
+
+/// Hands every regular file in the archive to `visit`, in archive order,
+/// until it returns false or fails. Every entry, directories and links
+/// included, is counted against `budget`. Returns false if stopped early.
+pub fn members<R: Read + Seek>(
+    format: Format,
+    reader: R,
+    budget: &mut Budget,
+    visit: &mut Visit,
+) -> Result<bool, Error> {
+    match format {
+        Format::Zip => zip_members(reader, budget, visit),
+        Format::Tar => tar_members(reader, budget, visit),
+        Format::TarGz => tar_members(MultiGzDecoder::new(reader), budget, visit),
+        Format::TarBz2 => tar_members(BzDecoder::new(reader), budget, visit),
+        Format::TarXz => tar_members(
+            XzReader::new_mem_limit(reader, true, XZ_MEMORY_LIMIT_KB),
+            budget,
+            visit,
+        ),
+    }
+}
+
+fn zip_members<R: Read + Seek>(
+    reader: R,
+    budget: &mut Budget,
+    visit: &mut Visit,
+) -> Result<bool, Error> {
+    let mut archive = ZipArchive::new(reader)?;
+    for index in 0..archive.len() {
+        budget.enter()?;
+        let mut file = archive.by_index(index)?;
+        if !file.is_file() {
+            continue;
+        }
+        let name = match file.name() {
+            Ok(name) => clean_name(&name),
+            Err(_) => continue,
+        };
+        let member = Member {
+            name,
+            size: file.size(),
+            reader: &mut file,
+        };
+        if !visit(member, budget)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whatever a member's visit leaves unread is still decompressed to get to
+/// the next one, so it is charged to the budget as well.
+fn tar_members(reader: impl Read, budget: &mut Budget, visit: &mut Visit) -> Result<bool, Error> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        budget.enter()?;
+        let mut entry = entry?;
+        let size = entry.size();
+        if !entry.header().entry_type().is_file() {
+            budget.charge_skipped(size)?;
+            continue;
+        }
+        let name = clean_name(&entry.path()?.to_string_lossy());
+        let mut reader = Counted {
+            reader: &mut entry,
+            count: 0,
+        };
+        let member = Member {
+            name,
+            size,
+            reader: &mut reader,
+        };
+        let more = visit(member, budget)?;
+        budget.charge_skipped(size.saturating_sub(reader.count))?;
+        if !more {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Counts the bytes read through it.
+struct Counted<R> {
+    reader: R,
+    count: u64,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+fn clean_name(name: &str) -> String {
+    name.trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Budget {
+        Budget {
+            limits,
+            size: 0,
+            entries: 0,
+        }
+    }
+
+    /// Counts one more member, page or embedded image.
+    pub fn enter(&mut self) -> Result<(), Error> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(Error::Limit(format!(
+                "more than {} entries (--max-archive-entries); the rest was not scanned",
+                self.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether an archive at this depth, counting the top-level one as 1,
+    /// may be opened.
+    pub fn may_open(&self, depth: usize) -> bool {
+        depth <= self.limits.max_depth
+    }
+
+    /// Bytes that may still be decompressed.
+    pub fn left(&self) -> u64 {
+        self.limits.max_size.saturating_sub(self.size)
+    }
+
+    /// Reads the rest of a member whose first bytes, `start`, were already
+    /// read, charging all of it to the budget. A member larger than
+    /// `MAX_MEMBER_SIZE` is unreadable.
+    pub fn read(&mut self, start: Vec<u8>, reader: &mut dyn Read) -> Result<Vec<u8>, Error> {
+        let mut bytes = start;
+        reader
+            .take(
+                self.left()
+                    .min(MAX_MEMBER_SIZE)
+                    .saturating_sub(bytes.len() as u64)
+                    .saturating_add(1),
+            )
+            .read_to_end(&mut bytes)?;
+        self.charge(bytes.len())?;
+        if bytes.len() as u64 > MAX_MEMBER_SIZE {
+            return Err(Error::Unreadable(format!(
+                "decompresses to more than {} MiB",
+                MAX_MEMBER_SIZE >> 20
+            )));
+        }
+        Ok(bytes)
+    }
+
+    /// Charges `len` bytes decompressed some other way.
+    pub fn charge(&mut self, len: usize) -> Result<(), Error> {
+        self.charge_skipped(len as u64)
+    }
+
+    /// Charges `len` bytes decompressed only to be skipped.
+    fn charge_skipped(&mut self, len: u64) -> Result<(), Error> {
+        self.size = self.size.saturating_add(len);
+        if self.size > self.limits.max_size {
+            return Err(Error::Limit(format!(
+                "decompresses to more than {} bytes (--max-archive-size); the rest was not \
+                 scanned",
+                self.limits.max_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::office;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn limits(max_size: u64, max_entries: u64) -> Limits {
+        Limits {
+            max_depth: 2,
+            max_size,
+            max_entries,
+        }
+    }
+
+    /// A zip archive of `(name, contents)`; names ending in `/` are
+    /// directories.
+    fn zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in members {
+            if name.ends_with('/') {
+                writer
+                    .add_directory(*name, SimpleFileOptions::default())
+                    .unwrap();
+            } else {
+                writer
+                    .start_file(*name, SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(contents).unwrap();
+            }
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn visit_all(bytes: &[u8], budget: &mut Budget) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        members(
+            Format::Zip,
+            Cursor::new(bytes),
+            budget,
+            &mut |member, budget| {
+                budget.read(Vec::new(), member.reader)?;
+                names.push(member.name);
+                Ok(true)
+            },
+        )?;
+        Ok(names)
+    }
+
+    #[test]
+    fn entries_within_the_limit() {
+        let bytes = zip(&[("a.png", b"a"), ("b.png", b"b")]);
+        let mut budget = Budget::new(limits(1 << 20, 2));
+        assert_eq!(visit_all(&bytes, &mut budget).unwrap(), ["a.png", "b.png"]);
+    }
+
+    #[test]
+    fn too_many_entries() {
+        let bytes = zip(&[("a.png", b"a"), ("b.png", b"b"), ("c.png", b"c")]);
+        let mut budget = Budget::new(limits(1 << 20, 2));
+        assert!(matches!(
+            visit_all(&bytes, &mut budget),
+            Err(Error::Limit(_))
+        ));
+    }
+
+    #[test]
+    fn directories_count_as_entries() {
+        let bytes = zip(&[("a/", b""), ("b/", b""), ("c/", b""), ("c/d.png", b"d")]);
+        let mut budget = Budget::new(limits(1 << 20, 3));
+        assert!(matches!(
+            visit_all(&bytes, &mut budget),
+            Err(Error::Limit(_))
+        ));
+    }
+
+    #[test]
+    fn too_many_bytes() {
+        let bytes = zip(&[("a.png", &[0; 600]), ("b.png", &[0; 600])]);
+        let mut budget = Budget::new(limits(1000, 10));
+        assert!(matches!(
+            visit_all(&bytes, &mut budget),
+            Err(Error::Limit(_))
+        ));
+    }
+
+    #[test]
+    fn read_stops_at_the_limit() {
+        let mut budget = Budget::new(limits(10, 10));
+        let result = budget.read(b"abc".to_vec(), &mut Cursor::new(vec![0; 1 << 20]));
+        assert!(matches!(result, Err(Error::Limit(_))));
+        assert_eq!(budget.left(), 0);
+    }
+
+    #[test]
+    fn skipped_tar_members_are_charged() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::fast(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4 << 20);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "x.bin", io::repeat(0).take(4 << 20))
+            .unwrap();
+        let bytes = builder.into_inner().unwrap().finish().unwrap();
+
+        let mut budget = Budget::new(limits(1 << 20, 10));
+        let result = members(
+            Format::TarGz,
+            Cursor::new(bytes),
+            &mut budget,
+            &mut |_, _| Ok(true),
+        );
+        assert!(matches!(result, Err(Error::Limit(_))));
+    }
+
+    #[test]
+    fn member_size_is_capped() {
+        let mut budget = Budget::new(limits(u64::MAX, 10));
+        let result = budget.read(Vec::new(), &mut io::repeat(0).take(MAX_MEMBER_SIZE + 1));
+        assert!(matches!(result, Err(Error::Unreadable(_))));
+    }
+
+    #[test]
+    fn depth() {
+        let budget = Budget::new(limits(10, 10));
+        assert!(budget.may_open(2));
+        assert!(!budget.may_open(3));
+    }
+
+    fn docx(media: usize) -> Vec<u8> {
+        let names: Vec<String> = (0..media)
+            .map(|i| format!("word/media/image{}.png", i))
+            .collect();
+        let mut members: Vec<(&str, &[u8])> = vec![("[Content_Types].xml", b"<Types/>")];
+        members.extend(names.iter().map(|name| (name.as_str(), &[0u8; 100][..])));
+        zip(&members)
+    }
+
+    #[test]
+    fn office_document_is_charged() {
+        let mut budget = Budget::new(limits(1 << 20, 5));
+        let result = office::extract(
+            Path::new("a.docx"),
+            &docx(10),
+            None,
+            &mut budget,
+            &mut |_| true,
+        );
+        assert!(matches!(result, Err(Error::Limit(_))));
+    }
+
+    #[test]
+    fn nested_office_document_shares_the_budget() {
+        // Each archive alone stays within the limit, both together do not.
+        let document = docx(3);
+        let outer = zip(&[("one.docx", &document), ("two.docx", &document)]);
+        let mut budget = Budget::new(limits(1 << 20, 8));
+        let result = members(
+            Format::Zip,
+            Cursor::new(outer),
+            &mut budget,
+            &mut |member, budget| {
+                let name = member.name.clone();
+                let bytes = budget.read(Vec::new(), member.reader)?;
+                office::extract(Path::new(&name), &bytes, None, budget, &mut |_| true)
+            },
+        );
+        assert!(matches!(result, Err(Error::Limit(_))));
+    }
+}
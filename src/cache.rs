@@ -0,0 +1,172 @@
+//! On-disk cache of OCR results, so that repeat scans only OCR new or changed
+//! images. Secret detection always re-runs on the cached lines, so rule
+//! changes take effect without invalidating anything.
+//!
+//! Entries are keyed by the SHA-256 of the image and live in a directory
+//! named after the OCR models and frame/page settings, so changing any of
+//! them starts a fresh cache. A separate index maps path, size and
+//! modification time to the content hash, which lets unchanged files skip
+//! even being read.
+//!
+//! The cache holds OCR text, and with it any secrets that were on screen, so
+//! everything is created readable by the owner only.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, DirBuilder, Metadata, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::UNIX_EPOCH;
+
+use crate::animation::FrameStats;
+use crate::{Dimensions, OcrLine};
+
+/// Bumped whenever the format of cache files changes.
+const CACHE_VERSION: u32 = 1;
+
+pub struct Cache {
+    /// Entries for the current models, by content hash.
+    entries: PathBuf,
+    /// Path to content hash, shared across models.
+    index: PathBuf,
+    /// Makes temporary file names unique within the process.
+    counter: AtomicUsize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub dimensions: Dimensions,
+    #[serde(default)]
+    pub frames: Option<FrameStats>,
+    #[serde(default)]
+    pub pages: Option<usize>,
+    pub lines: Vec<OcrLine>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    path: String,
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    sha256: String,
+}
+
+impl Cache {
+    /// `$XDG_CACHE_HOME/evileye/ocr`.
+    pub fn default_dir() -> Option<PathBuf> {
+        crate::models::cache_home().map(|dir| dir.join("evileye").join("ocr"))
+    }
+
+    /// Opens (creating if needed) the cache in `dir` for OCR results that
+    /// depend on `settings`: the SHA-256s of the models and anything else
+    /// that changes what OCR returns.
+    pub fn open(dir: &Path, settings: &[&str]) -> Result<Cache> {
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        for setting in settings {
+            hasher.update([0]);
+            hasher.update(setting);
+        }
+        let fingerprint = format!("{:x}", hasher.finalize());
+        let root = dir.join(format!("v{}", CACHE_VERSION));
+        let cache = Cache {
+            entries: root.join("models").join(&fingerprint[..16]),
+            index: root.join("index"),
+            counter: AtomicUsize::new(0),
+        };
+        for dir in [&cache.entries, &cache.index] {
+            create_private_dir(dir)
+                .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        }
+        Ok(cache)
+    }
+
+    /// The content hash recorded for `path`, if the file has not changed
+    /// since.
+    pub fn lookup_file(&self, path: &Path, metadata: &Metadata) -> Option<String> {
+        let key = self.index_path(path);
+        let entry: IndexEntry = serde_json::from_slice(&fs::read(key).ok()?).ok()?;
+        let (mtime_secs, mtime_nanos) = mtime(metadata)?;
+        (entry.path == path.to_string_lossy()
+            && entry.size == metadata.len()
+            && entry.mtime_secs == mtime_secs
+            && entry.mtime_nanos == mtime_nanos)
+            .then_some(entry.sha256)
+    }
+
+    /// Remembers that `path`, as described by `metadata`, has content `sha256`.
+    pub fn record_file(&self, path: &Path, metadata: &Metadata, sha256: &str) -> Result<()> {
+        let Some((mtime_secs, mtime_nanos)) = mtime(metadata) else {
+            return Ok(());
+        };
+        let entry = IndexEntry {
+            path: path.to_string_lossy().to_string(),
+            size: metadata.len(),
+            mtime_secs,
+            mtime_nanos,
+            sha256: sha256.to_string(),
+        };
+        self.write(&self.index_path(path), &serde_json::to_vec(&entry)?)
+    }
+
+    pub fn get(&self, sha256: &str) -> Option<Entry> {
+        serde_json::from_slice(&fs::read(self.entry_path(sha256)).ok()?).ok()
+    }
+
+    pub fn put(&self, sha256: &str, entry: &Entry) -> Result<()> {
+        self.write(&self.entry_path(sha256), &serde_json::to_vec(entry)?)
+    }
+
+    fn entry_path(&self, sha256: &str) -> PathBuf {
+        self.entries.join(format!("{}.json", sha256))
+    }
+
+    fn index_path(&self, path: &Path) -> PathBuf {
+        let key = Sha256::digest(path.to_string_lossy().as_bytes());
+        self.index.join(format!("{:x}.json", key))
+    }
+
+    /// Writes through a temporary file so that concurrent readers never see
+    /// a partial entry.
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let temp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            self.counter.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let result = options
+            .open(&temp)
+            .and_then(|mut file| file.write_all(contents))
+            .and_then(|_| fs::rename(&temp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result.with_context(|| format!("Failed to write cache file {}", path.display()))
+    }
+}
+
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+fn mtime(metadata: &Metadata) -> Option<(u64, u32)> {
+    let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
@@ -0,0 +1,748 @@
+//! Finds the images to scan under the given roots.
+//!
+//! Files with a known image extension are always picked up. Everything else
+//! is recognized by its magic bytes, since chat apps and browser caches often
+//! store screenshots without an extension (or with the wrong one). PDFs,
+//! Office documents and archives are handed on whole; the decoders call
+//! `Discovery::expand` to get at the images and text inside.
+
+use anyhow::{Context, Result};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+use image::io::Reader as ImageReader;
+use image::ImageFormat;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+use crate::archive::{self, Budget, Limits, Member};
+use crate::{office, pdf};
+use crate::{ErrorKind, OcrLine, Placement, ScanError, Skipped};
+
+/// Extensions that are scanned without looking at the file contents. The
+/// decoder still goes by the contents, so a mislabelled image is fine.
+const IMAGE_EXTENSIONS: [&str; 19] = [
+    "apng", "bmp", "gif", "ico", "jfif", "jpe", "jpeg", "jpg", "pbm", "pgm", "png", "pjpeg", "pnm",
+    "ppm", "qoi", "tga", "tif", "tiff", "webp",
+];
+
+/// Formats recognized by magic bytes in files without a known extension.
+/// Formats with very short or ambiguous signatures (BMP, ICO, PNM, TGA) are
+/// left out to avoid mistaking arbitrary files for images.
+const SNIFFED_FORMATS: [ImageFormat; 6] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+    ImageFormat::Tiff,
+    ImageFormat::Qoi,
+];
+
+/// Enough for every signature `image::guess_format` knows about, the name of
+/// the first member of a zip archive and the magic in a tar header.
+const SNIFF_LEN: usize = 512;
+
+/// Something to scan, as handed from discovery to the decoders.
+pub enum Input {
+    File(PathBuf),
+    /// An image that is already in memory, reported under `name`.
+    Bytes {
+        name: String,
+        bytes: Vec<u8>,
+        /// Where the document it was extracted from shows it.
+        placement: Option<Placement>,
+    },
+    /// Text that needs no OCR, such as a PDF's text layer.
+    Text {
+        name: String,
+        lines: Vec<OcrLine>,
+    },
+}
+
+/// Something found inside a document.
+pub enum Item {
+    Input(Input),
+    /// An embedded image or page that could not be extracted.
+    Skipped(Skipped),
+}
+
+impl Input {
+    /// The path the image is reported under.
+    pub fn path(&self) -> &Path {
+        match self {
+            Input::File(path) => path,
+            Input::Bytes { name, .. } | Input::Text { name, .. } => Path::new(name),
+        }
+    }
+}
+
+pub struct Discovery {
+    /// Directories to walk, or files to scan directly.
+    pub roots: Vec<PathBuf>,
+    /// Lowercased, without the leading dot.
+    pub image_extensions: HashSet<String>,
+    /// `--include`/`--exclude` globs, matched relative to each root.
+    pub globs: Arc<Override>,
+    /// Honor `.gitignore`, `.ignore` and git exclude files.
+    pub respect_ignore: bool,
+    pub max_depth: Option<usize>,
+    pub one_file_system: bool,
+    /// Descend into symlinked directories. Symlinked files are always
+    /// scanned.
+    pub follow_symlinks: bool,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Images whose width and height are both below this are skipped.
+    pub min_dimension: Option<u32>,
+    /// Paths already scanned by an earlier run (`--resume`).
+    pub done: HashSet<String>,
+    pub archives: Limits,
+}
+
+/// A file with images inside rather than an image itself.
+#[derive(Debug, Clone, Copy)]
+pub enum Container {
+    Pdf,
+    Office,
+    Archive(archive::Format),
+}
+
+/// What the walk hands on to the decoders.
+pub enum Found {
+    Input(Input),
+    /// Expanded by the decoders rather than the walk, so that a malformed
+    /// document can only fail itself.
+    Document(PathBuf, Container),
+}
+
+/// What a file found by the walk turned out to be.
+#[derive(Clone, Copy)]
+enum Kind {
+    Image,
+    Container(Container),
+}
+
+impl Discovery {
+    /// Walks every root and sends each image or document to `tx` as soon as
+    /// it is found. Blocks while the pipeline is busy and stops once nobody
+    /// is listening or the scan is cancelled.
+    /// Returns everything that could not be looked at.
+    pub fn walk(
+        &self,
+        tx: &mpsc::Sender<Found>,
+        cancelled: &watch::Receiver<bool>,
+    ) -> Vec<Skipped> {
+        let mut walk = Walk {
+            discovery: self,
+            tx,
+            cancelled,
+            skipped: Vec::new(),
+        };
+        for root in &self.roots {
+            if !walk.root(root) {
+                break;
+            }
+        }
+        walk.skipped
+    }
+
+    /// Hands every image and text layer inside the document at `path` to
+    /// `emit`, until it returns false or the scan is cancelled. What could
+    /// not be looked at is added to `skipped` as it is found.
+    pub fn expand(
+        &self,
+        path: &Path,
+        container: Container,
+        cancelled: &watch::Receiver<bool>,
+        emit: &mut dyn FnMut(Input) -> bool,
+        skipped: &mut Vec<Skipped>,
+    ) {
+        let mut expansion = Expansion {
+            discovery: self,
+            cancelled,
+            emit,
+            skipped,
+        };
+        match container {
+            Container::Archive(format) => {
+                expansion.archive(path, format);
+            }
+            container => match std::fs::read(path) {
+                Ok(bytes) => {
+                    let mut budget = Budget::new(self.archives);
+                    if let Err(err) = expansion.extract(path, &bytes, container, &mut budget) {
+                        expansion.skip(path, archive_error_kind(&err), err.to_string());
+                    }
+                }
+                Err(err) => expansion.skip(path, ErrorKind::of_io(&err), err.to_string()),
+            },
+        }
+    }
+
+    fn has_image_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|ext| self.image_extensions.contains(&ext.to_lowercase()))
+    }
+
+    fn is_done(&self, path: &Path) -> bool {
+        self.done.contains(path.to_string_lossy().as_ref())
+    }
+
+    /// What a file is by its name alone, if that is enough to tell.
+    fn kind_by_name(&self, path: &Path) -> Option<Kind> {
+        if self.has_image_extension(path) {
+            Some(Kind::Image)
+        } else if office::has_extension(path) {
+            Some(Kind::Container(Container::Office))
+        } else {
+            archive::from_name(path)
+                .filter(|_| self.archives.max_depth > 0)
+                .map(|format| Kind::Container(Container::Archive(format)))
+        }
+    }
+
+    /// What the file at `path` is by its first `SNIFF_LEN` bytes. Archives
+    /// are only recognized this way without an extension, so that `.jar`,
+    /// `.apk`, `.epub` and other zip-based formats are left alone.
+    fn kind_by_header(&self, path: &Path, header: &[u8]) -> Option<Kind> {
+        if pdf::is_pdf(header) {
+            Some(Kind::Container(Container::Pdf))
+        } else if office::is_office(header) {
+            Some(Kind::Container(Container::Office))
+        } else if sniff(header).is_some() {
+            Some(Kind::Image)
+        } else {
+            archive::from_header(header)
+                .filter(|_| self.archives.max_depth > 0 && path.extension().is_none())
+                .map(|format| Kind::Container(Container::Archive(format)))
+        }
+    }
+
+    /// Reads just the image header. Images whose size cannot be determined
+    /// are let through so that decoding reports the problem.
+    fn too_small(&self, path: &Path) -> bool {
+        let Some(min) = self.min_dimension else {
+            return false;
+        };
+        let dimensions = ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok());
+        dimensions.is_some_and(|(width, height)| width < min && height < min)
+    }
+}
+
+struct Walk<'a> {
+    discovery: &'a Discovery,
+    tx: &'a mpsc::Sender<Found>,
+    cancelled: &'a watch::Receiver<bool>,
+    skipped: Vec<Skipped>,
+}
+
+impl Walk<'_> {
+    /// Returns false once nobody is listening anymore or the scan was
+    /// cancelled.
+    fn root(&mut self, root: &Path) -> bool {
+        let discovery = self.discovery;
+        let globs = Arc::clone(&discovery.globs);
+        let walk_root = root.to_path_buf();
+        let walker = WalkBuilder::new(root)
+            .standard_filters(false)
+            .git_ignore(discovery.respect_ignore)
+            .git_exclude(discovery.respect_ignore)
+            .git_global(discovery.respect_ignore)
+            .ignore(discovery.respect_ignore)
+            .parents(discovery.respect_ignore)
+            .require_git(false)
+            // Roots themselves are never filtered out.
+            .filter_entry(move |entry| {
+                let path = entry
+                    .path()
+                    .strip_prefix(&walk_root)
+                    .unwrap_or(entry.path());
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                entry.depth() == 0 || !globs.matched(path, is_dir).is_ignore()
+            })
+            .max_depth(discovery.max_depth)
+            .same_file_system(discovery.one_file_system)
+            .follow_links(discovery.follow_symlinks)
+            .build();
+
+        for entry in walker {
+            if *self.cancelled.borrow() {
+                return false;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = error_path(&err).unwrap_or(root).to_path_buf();
+                    match err.io_error() {
+                        Some(io) if path.is_symlink() && !path.exists() => {
+                            self.skip(&path, ErrorKind::BrokenSymlink, io.to_string())
+                        }
+                        Some(io) => self.skip(&path, ErrorKind::of_io(io), io.to_string()),
+                        None => self.skip(&path, ErrorKind::Io, err.to_string()),
+                    }
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let sent = match self.kind(path, entry.path_is_symlink()) {
+                Some(Kind::Image) => {
+                    discovery.is_done(path)
+                        || discovery.too_small(path)
+                        || self
+                            .tx
+                            .blocking_send(Found::Input(Input::File(path.to_path_buf())))
+                            .is_ok()
+                }
+                // What is done is decided per image inside.
+                Some(Kind::Container(container)) => self
+                    .tx
+                    .blocking_send(Found::Document(path.to_path_buf(), container))
+                    .is_ok(),
+                None => true,
+            };
+            if !sent {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn kind(&mut self, path: &Path, is_symlink: bool) -> Option<Kind> {
+        let discovery = self.discovery;
+        // Follows symlinks, so linked images are scanned too.
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if is_symlink => {
+                self.skip(path, ErrorKind::BrokenSymlink, err.to_string());
+                return None;
+            }
+            Err(err) => {
+                self.skip(path, ErrorKind::of_io(&err), err.to_string());
+                return None;
+            }
+        };
+        // Empty files cannot be images; this also keeps the sniffing away
+        // from most of /proc.
+        if !metadata.is_file() || metadata.len() == 0 {
+            return None;
+        }
+        if discovery.min_size.is_some_and(|min| metadata.len() < min)
+            || discovery.max_size.is_some_and(|max| metadata.len() > max)
+        {
+            return None;
+        }
+
+        if let Some(kind) = discovery.kind_by_name(path) {
+            return Some(kind);
+        }
+        let header = match read_header(path) {
+            Ok(header) => header,
+            // Only a PDF is worth reporting: any other file without a known
+            // extension is far more likely not to be scannable at all.
+            Err(err) => {
+                if is_pdf_name(path) {
+                    self.skip(path, ErrorKind::of_io(&err), err.to_string());
+                }
+                return None;
+            }
+        };
+        discovery.kind_by_header(path, &header)
+    }
+
+    fn skip(&mut self, path: &Path, kind: ErrorKind, message: String) {
+        skip(&mut self.skipped, path, kind, message);
+    }
+}
+
+/// Looks inside one document or archive for `Discovery::expand`.
+struct Expansion<'a> {
+    discovery: &'a Discovery,
+    cancelled: &'a watch::Receiver<bool>,
+    emit: &'a mut dyn FnMut(Input) -> bool,
+    skipped: &'a mut Vec<Skipped>,
+}
+
+impl Expansion<'_> {
+    /// Hands on what was found in a PDF or Office document that is already
+    /// in memory. An unreadable document is skipped right here, but running
+    /// out of `budget` is an error about the outermost archive or document.
+    /// Returns false once nobody is listening anymore.
+    fn extract(
+        &mut self,
+        path: &Path,
+        bytes: &[u8],
+        container: Container,
+        budget: &mut Budget,
+    ) -> Result<bool, archive::Error> {
+        let discovery = self.discovery;
+        let min_dimension = discovery.min_dimension;
+        let mut listening = true;
+        let mut skipped = Vec::new();
+        let emit = &mut *self.emit;
+        let mut emit = |item| {
+            match item {
+                Item::Input(input) if !discovery.is_done(input.path()) => {
+                    listening = emit(input);
+                }
+                Item::Input(_) => {}
+                Item::Skipped(skip) => skipped.push(skip),
+            }
+            listening
+        };
+        let (result, what) = match container {
+            Container::Pdf => (
+                pdf::extract(path, bytes, min_dimension, budget, &mut emit),
+                "PDF",
+            ),
+            _ => (
+                office::extract(path, bytes, min_dimension, budget, &mut emit),
+                "Office document",
+            ),
+        };
+        for skip in skipped {
+            self.skip(Path::new(&skip.path), skip.error.kind, skip.error.message);
+        }
+        match result {
+            Err(archive::Error::Unreadable(message)) => {
+                let message = format!("unreadable {}: {}", what, message);
+                self.skip(path, ErrorKind::Decode, message);
+                Ok(listening)
+            }
+            result => result.map(|_| listening),
+        }
+    }
+
+    /// Hands on everything worth scanning inside an archive on disk.
+    /// Returns false once nobody is listening anymore or the scan was
+    /// cancelled.
+    fn archive(&mut self, path: &Path, format: archive::Format) -> bool {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                self.skip(path, ErrorKind::of_io(&err), err.to_string());
+                return true;
+            }
+        };
+        let mut budget = Budget::new(self.discovery.archives);
+        let name = path.to_string_lossy();
+        match self.archive_members(&name, format, BufReader::new(file), 1, &mut budget) {
+            Ok(listening) => listening,
+            Err(err) => {
+                self.skip(path, archive_error_kind(&err), err.to_string());
+                true
+            }
+        }
+    }
+
+    /// Looks at every member of the archive reported as `name`, `depth`
+    /// archives deep. Errors are about the outermost archive: an unreadable
+    /// nested archive is skipped right here.
+    fn archive_members<R: Read + Seek>(
+        &mut self,
+        name: &str,
+        format: archive::Format,
+        reader: R,
+        depth: usize,
+        budget: &mut Budget,
+    ) -> Result<bool, archive::Error> {
+        archive::members(format, reader, budget, &mut |member, budget| {
+            self.member(name, member, depth, budget)
+        })
+    }
+
+    fn member(
+        &mut self,
+        archive: &str,
+        mut member: Member,
+        depth: usize,
+        budget: &mut Budget,
+    ) -> Result<bool, archive::Error> {
+        if *self.cancelled.borrow() {
+            return Ok(false);
+        }
+        let discovery = self.discovery;
+        if member.size == 0
+            || discovery.min_size.is_some_and(|min| member.size < min)
+            || discovery.max_size.is_some_and(|max| member.size > max)
+        {
+            return Ok(true);
+        }
+        let path = format!("{}!/{}", archive, member.name);
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        Read::take(&mut member.reader, SNIFF_LEN as u64).read_to_end(&mut header)?;
+        let Some(kind) = discovery
+            .kind_by_name(Path::new(&path))
+            .or_else(|| discovery.kind_by_header(Path::new(&path), &header))
+        else {
+            return Ok(true);
+        };
+        if matches!(kind, Kind::Container(Container::Archive(_))) && !budget.may_open(depth + 1) {
+            let message = "nested deeper than --max-archive-depth".to_string();
+            self.skip(Path::new(&path), ErrorKind::Limit, message);
+            return Ok(true);
+        }
+
+        let bytes = match budget.read(header, member.reader) {
+            Ok(bytes) => bytes,
+            Err(archive::Error::Unreadable(message)) => {
+                self.skip(Path::new(&path), ErrorKind::Decode, message);
+                return Ok(true);
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(match kind {
+            Kind::Image => {
+                discovery.is_done(Path::new(&path))
+                    || too_small(&bytes, discovery.min_dimension)
+                    || (self.emit)(Input::Bytes {
+                        name: path,
+                        bytes,
+                        placement: None,
+                    })
+            }
+            Kind::Container(Container::Archive(format)) => {
+                let reader = Cursor::new(bytes);
+                match self.archive_members(&path, format, reader, depth + 1, budget) {
+                    Err(err @ archive::Error::Unreadable(_)) => {
+                        self.skip(Path::new(&path), ErrorKind::Decode, err.to_string());
+                        true
+                    }
+                    result => result?,
+                }
+            }
+            Kind::Container(container) => {
+                self.extract(Path::new(&path), &bytes, container, budget)?
+            }
+        })
+    }
+
+    fn skip(&mut self, path: &Path, kind: ErrorKind, message: String) {
+        skip(self.skipped, path, kind, message);
+    }
+}
+
+/// Records `path` as skipped, with a warning on stderr.
+pub fn skip(skipped: &mut Vec<Skipped>, path: &Path, kind: ErrorKind, message: String) {
+    let error = ScanError { kind, message };
+    eprintln!("warning: skipped {}: {}", path.display(), error);
+    skipped.push(Skipped {
+        path: path.to_string_lossy().to_string(),
+        error,
+    });
+}
+
+/// Reads a list of paths from `source` (`-` for stdin), separated by NULs if
+/// there are any (`find -print0`, `git ls-files -z`) and by newlines
+/// otherwise. Blank lines are ignored.
+pub fn read_path_list(source: &Path) -> Result<Vec<PathBuf>> {
+    let contents = if source == Path::new("-") {
+        let mut contents = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut contents)
+            .context("Failed to read the path list from stdin")?;
+        contents
+    } else {
+        std::fs::read(source)
+            .with_context(|| format!("Failed to read path list {}", source.display()))?
+    };
+    Ok(parse_path_list(&contents))
+}
+
+fn parse_path_list(contents: &[u8]) -> Vec<PathBuf> {
+    let separator = if contents.contains(&0) { 0 } else { b'\n' };
+    contents
+        .split(|&b| b == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The built-in image extensions plus `extra`.
+pub fn image_extensions(extra: &[String]) -> HashSet<String> {
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|ext| ext.to_string())
+        .chain(
+            extra
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase()),
+        )
+        .collect()
+}
+
+/// Builds the `--include`/`--exclude` matcher, for paths relative to a root.
+/// When includes are given, only files matching one of them are scanned;
+/// excludes win over includes and also prune whole directories.
+pub fn overrides(include: &[String], exclude: &[String]) -> Result<Override> {
+    let mut builder = OverrideBuilder::new(".");
+    for glob in include {
+        builder
+            .add(glob)
+            .with_context(|| format!("Invalid --include glob {:?}", glob))?;
+    }
+    for glob in exclude {
+        builder
+            .add(&format!("!{}", glob))
+            .with_context(|| format!("Invalid --exclude glob {:?}", glob))?;
+    }
+    Ok(builder.build()?)
+}
+
+/// Parses a byte count with an optional `K`, `M` or `G` suffix (powers of
+/// 1024), e.g. `500K`.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    let multiplier = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches(['B', 'I'])
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("unknown size unit {:?}", unit)),
+    };
+    number
+        .parse::<u64>()
+        .map_err(|e| e.to_string())?
+        .checked_mul(multiplier)
+        .ok_or_else(|| "size too large".to_string())
+}
+
+/// Whether an image in memory has a width and height both below
+/// `min_dimension`. Images whose size cannot be determined are let through
+/// so that decoding reports the problem.
+pub fn too_small(bytes: &[u8], min_dimension: Option<u32>) -> bool {
+    let Some(min) = min_dimension else {
+        return false;
+    };
+    let dimensions = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    dimensions.is_some_and(|(width, height)| width < min && height < min)
+}
+
+fn archive_error_kind(err: &archive::Error) -> ErrorKind {
+    match err {
+        archive::Error::Unreadable(_) => ErrorKind::Decode,
+        archive::Error::Limit(_) => ErrorKind::Limit,
+    }
+}
+
+fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        ignore::Error::Loop { child, .. } => Some(child),
+        _ => None,
+    }
+}
+
+fn is_pdf_name(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+fn read_header(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Returns the image format of a file starting with `header` if it carries
+/// the signature of one of `SNIFFED_FORMATS`.
+fn sniff(header: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(header)
+        .ok()
+        .filter(|format| SNIFFED_FORMATS.contains(format) && format.reading_enabled())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(list: &[u8]) -> Vec<PathBuf> {
+        parse_path_list(list)
+    }
+
+    #[test]
+    fn newline_separated_paths() {
+        assert_eq!(
+            paths(b"a.png\nshots/b c.png\r\n\n\nd.png"),
+            ["a.png", "shots/b c.png", "d.png"].map(PathBuf::from)
+        );
+        assert!(paths(b"").is_empty());
+        assert!(paths(b"\n\n").is_empty());
+    }
+
+    #[test]
+    fn nul_separated_paths() {
+        // Newlines are part of a name once there is a NUL.
+        assert_eq!(
+            paths(b"a.png\0odd\nname.png\0\0d.png\0"),
+            ["a.png", "odd\nname.png", "d.png"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn path_list_file() {
+        let dir = std::env::temp_dir().join(format!("evileye-paths-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let list = dir.join("list");
+        std::fs::write(&list, b"a.png\0b.png\0").unwrap();
+        let result = read_path_list(&list);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(result.unwrap(), ["a.png", "b.png"].map(PathBuf::from));
+        assert!(read_path_list(&dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("1500"), Ok(1500));
+        assert_eq!(parse_size("500K"), Ok(500 << 10));
+        assert_eq!(parse_size("2m"), Ok(2 << 20));
+        assert_eq!(parse_size(" 1 GiB "), Ok(1 << 30));
+        assert_eq!(parse_size("3KB"), Ok(3 << 10));
+        assert_eq!(parse_size("7B"), Ok(7));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("K").is_err());
+        assert!(parse_size("12T").is_err());
+        assert!(parse_size("1.5M").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+    }
+}
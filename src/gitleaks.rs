@@ -0,0 +1,386 @@
+//! Translates gitleaks (v8) configuration files into EvilEye rules.
+//!
+//! Settings that have no meaning when scanning images (commit allowlists,
+//! path-only rules, ...) are reported as warnings instead of being dropped
+//! silently.
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::rules::{Allowlist, RegexTarget, Rule, Severity};
+
+/// How many `[extend] path` hops are followed before giving up.
+const MAX_EXTEND_DEPTH: usize = 8;
+
+pub struct GitleaksConfig {
+    pub rules: Vec<Rule>,
+    pub allowlists: Vec<Allowlist>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigFile {
+    #[serde(default)]
+    title: String,
+    extend: Option<ExtendConfig>,
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    allowlist: Option<AllowlistConfig>,
+    #[serde(default)]
+    allowlists: Vec<AllowlistConfig>,
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExtendConfig {
+    #[serde(default)]
+    use_default: bool,
+    path: Option<String>,
+    #[serde(default)]
+    disabled_rules: Vec<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuleConfig {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    description: String,
+    regex: Option<String>,
+    secret_group: Option<usize>,
+    entropy: Option<f64>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    path: Option<String>,
+    allowlist: Option<AllowlistConfig>,
+    #[serde(default)]
+    allowlists: Vec<AllowlistConfig>,
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AllowlistConfig {
+    // Accepted so it is not reported as unsupported.
+    #[allow(dead_code)]
+    #[serde(default)]
+    description: String,
+    condition: Option<String>,
+    regex_target: Option<String>,
+    #[serde(default)]
+    regexes: Vec<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    commits: Vec<String>,
+    #[serde(default)]
+    stopwords: Vec<String>,
+    #[serde(default)]
+    target_rules: Vec<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, toml::Value>,
+}
+
+pub fn load(path: &Path) -> Result<GitleaksConfig> {
+    load_with_depth(path, 0)
+}
+
+fn load_with_depth(path: &Path, depth: usize) -> Result<GitleaksConfig> {
+    if depth > MAX_EXTEND_DEPTH {
+        bail!("{}: too many nested [extend] paths", path.display());
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read gitleaks config {}", path.display()))?;
+    let source = path.display().to_string();
+    let file: ConfigFile = toml::from_str(&contents).map_err(|e| anyhow!("{}: {}", source, e))?;
+
+    let mut config = GitleaksConfig {
+        rules: Vec::new(),
+        allowlists: Vec::new(),
+        warnings: Vec::new(),
+    };
+    report_unsupported(&mut config.warnings, &source, &file.other);
+
+    if let Some(extend) = file.extend {
+        let at = format!("{}: [extend]", source);
+        report_unsupported(&mut config.warnings, &at, &extend.other);
+        if extend.use_default {
+            config.warnings.push(format!(
+                "{}: useDefault is not supported; the gitleaks default rules are not bundled",
+                at
+            ));
+        }
+        if let Some(base) = extend.path {
+            let base = path.parent().unwrap_or(Path::new(".")).join(base);
+            let base = load_with_depth(&base, depth + 1)?;
+            config.warnings.extend(base.warnings);
+            config.allowlists.extend(base.allowlists);
+            config.rules.extend(
+                base.rules
+                    .into_iter()
+                    .filter(|r| !extend.disabled_rules.contains(&r.id)),
+            );
+        } else if !extend.disabled_rules.is_empty() {
+            config.warnings.push(format!(
+                "{}: disabledRules only applies to rules from an extended path",
+                at
+            ));
+        }
+    }
+
+    for (index, rule) in file.rules.into_iter().enumerate() {
+        let at = format!("{}: rule #{} (id {:?})", source, index + 1, rule.id);
+        if let Some(rule) = translate_rule(&at, rule, &mut config.warnings)? {
+            match config.rules.iter_mut().find(|r| r.id == rule.id) {
+                Some(existing) => *existing = rule,
+                None => config.rules.push(rule),
+            }
+        }
+    }
+
+    for (index, allowlist) in file
+        .allowlist
+        .into_iter()
+        .chain(file.allowlists)
+        .enumerate()
+    {
+        let at = format!("{}: allowlist #{}", source, index + 1);
+        config
+            .allowlists
+            .push(translate_allowlist(&at, allowlist, &mut config.warnings)?);
+    }
+
+    if !file.title.is_empty() && config.rules.is_empty() {
+        config.warnings.push(format!(
+            "{}: {:?} defines no usable rules",
+            source, file.title
+        ));
+    }
+
+    Ok(config)
+}
+
+fn translate_rule(at: &str, rule: RuleConfig, warnings: &mut Vec<String>) -> Result<Option<Rule>> {
+    if rule.id.trim().is_empty() {
+        bail!("{}: id must not be empty", at);
+    }
+    report_unsupported(warnings, at, &rule.other);
+
+    let Some(pattern) = rule.regex else {
+        warnings.push(format!(
+            "{}: rules without a regex (path-only rules) are not supported; skipped",
+            at
+        ));
+        return Ok(None);
+    };
+    if rule.path.is_some() {
+        warnings.push(format!(
+            "{}: path is not applied to images; the rule runs on every image",
+            at
+        ));
+    }
+
+    let regex = Regex::new(&pattern).with_context(|| format!("{}: invalid regex", at))?;
+    let groups = regex.captures_len() - 1;
+    let secret_group = match rule.secret_group {
+        Some(group) if group > groups => bail!(
+            "{}: secretGroup {} but the regex only has {} capture group(s)",
+            at,
+            group,
+            groups
+        ),
+        // gitleaks reads an unset secretGroup as 0, and then reports the
+        // capture group only when it is the only one.
+        Some(0) | None if groups == 1 => Some(1),
+        Some(0) | None => None,
+        Some(group) => Some(group),
+    };
+
+    let mut allowlists = Vec::new();
+    for (index, allowlist) in rule
+        .allowlist
+        .into_iter()
+        .chain(rule.allowlists)
+        .enumerate()
+    {
+        let at = format!("{}: allowlist #{}", at, index + 1);
+        let allowlist = translate_allowlist(&at, allowlist, warnings)?;
+        if !allowlist.target_rules.is_empty() {
+            warnings.push(format!("{}: targetRules is ignored on rule allowlists", at));
+        }
+        allowlists.push(allowlist);
+    }
+
+    Ok(Some(Rule {
+        id: rule.id,
+        description: rule.description,
+        regex,
+        secret_group,
+        entropy: rule.entropy,
+        keywords: rule.keywords.iter().map(|k| k.to_lowercase()).collect(),
+        severity: Severity::default(),
+        tags: rule.tags,
+        allowlists,
+    }))
+}
+
+fn translate_allowlist(
+    at: &str,
+    allowlist: AllowlistConfig,
+    warnings: &mut Vec<String>,
+) -> Result<Allowlist> {
+    report_unsupported(warnings, at, &allowlist.other);
+    if !allowlist.commits.is_empty() {
+        warnings.push(format!(
+            "{}: commits do not apply to images and are ignored",
+            at
+        ));
+    }
+
+    let require_all = match allowlist
+        .condition
+        .as_deref()
+        .map(str::to_uppercase)
+        .as_deref()
+    {
+        None | Some("OR") => false,
+        Some("AND") => true,
+        Some(other) => bail!("{}: unknown condition {:?}", at, other),
+    };
+    let regex_target = match allowlist.regex_target.as_deref() {
+        None | Some("secret") => RegexTarget::Secret,
+        Some("match") => RegexTarget::Match,
+        Some("line") => RegexTarget::Line,
+        Some(other) => bail!("{}: unknown regexTarget {:?}", at, other),
+    };
+    let compile = |patterns: &[String], what: &str| {
+        patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("{}: invalid {} {:?}", at, what, p)))
+            .collect::<Result<Vec<_>>>()
+    };
+
+    Ok(Allowlist {
+        require_all,
+        regex_target,
+        regexes: compile(&allowlist.regexes, "regex")?,
+        paths: compile(&allowlist.paths, "path")?,
+        stopwords: allowlist
+            .stopwords
+            .iter()
+            .map(|w| w.to_lowercase())
+            .collect(),
+        target_rules: allowlist.target_rules,
+    })
+}
+
+fn report_unsupported(warnings: &mut Vec<String>, at: &str, other: &BTreeMap<String, toml::Value>) {
+    for key in other.keys() {
+        warnings.push(format!("{}: unsupported field `{}` ignored", at, key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate(toml: &str) -> (Result<Option<Rule>>, Vec<String>) {
+        let rule: RuleConfig = toml::from_str(toml).unwrap();
+        let mut warnings = Vec::new();
+        (translate_rule("rule", rule, &mut warnings), warnings)
+    }
+
+    fn secret_group(toml: &str) -> Option<usize> {
+        translate(toml).0.unwrap().unwrap().secret_group
+    }
+
+    #[test]
+    fn secret_group_defaults_to_the_only_group() {
+        assert_eq!(secret_group("id = 'a'\nregex = 'key=(\\w+)'"), Some(1));
+    }
+
+    #[test]
+    fn secret_group_defaults_to_the_match() {
+        assert_eq!(secret_group("id = 'a'\nregex = 'key=\\w+'"), None);
+        assert_eq!(secret_group("id = 'a'\nregex = '(key)=(\\w+)'"), None);
+        // Non-capturing groups do not count.
+        assert_eq!(secret_group("id = 'a'\nregex = '(?:key)=\\w+'"), None);
+    }
+
+    #[test]
+    fn secret_group_given() {
+        assert_eq!(
+            secret_group("id = 'a'\nregex = '(key)=(\\w+)'\nsecretGroup = 2"),
+            Some(2)
+        );
+        // Explicitly 0 is the same as unset.
+        assert_eq!(
+            secret_group("id = 'a'\nregex = 'key=(\\w+)'\nsecretGroup = 0"),
+            Some(1)
+        );
+        assert_eq!(
+            secret_group("id = 'a'\nregex = '(key)=(\\w+)'\nsecretGroup = 0"),
+            None
+        );
+    }
+
+    #[test]
+    fn secret_group_out_of_range() {
+        let (result, _) = translate("id = 'a'\nregex = 'key=(\\w+)'\nsecretGroup = 2");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn path_only_rule_is_skipped() {
+        let (result, warnings) = translate("id = 'a'\npath = '\\.pem$'");
+        assert!(result.unwrap().is_none());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn rule_allowlists() {
+        let (result, warnings) = translate(
+            "id = 'a'\nregex = 'key=(\\w+)'\n\
+             [allowlist]\nregexes = ['^EXAMPLE']\nstopwords = ['Dummy']\n\
+             [[allowlists]]\ncondition = 'AND'\nregexTarget = 'line'\nregexes = ['test']\n\
+             commits = ['abc']\ntargetRules = ['b']",
+        );
+        let rule = result.unwrap().unwrap();
+        assert_eq!(rule.allowlists.len(), 2);
+
+        let first = &rule.allowlists[0];
+        assert!(!first.require_all);
+        assert_eq!(first.regex_target, RegexTarget::Secret);
+        assert!(first.regexes[0].is_match("EXAMPLEKEY"));
+        assert_eq!(first.stopwords, ["dummy"]);
+
+        let second = &rule.allowlists[1];
+        assert!(second.require_all);
+        assert_eq!(second.regex_target, RegexTarget::Line);
+        // Commits and targetRules make no sense here, so they are reported.
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn unknown_allowlist_settings() {
+        let (result, _) = translate("id = 'a'\nregex = 'x'\n[allowlist]\ncondition = 'XOR'");
+        assert!(result.is_err());
+        let (result, _) = translate("id = 'a'\nregex = 'x'\n[allowlist]\nregexTarget = 'path'");
+        assert!(result.is_err());
+        let (result, warnings) = translate("id = 'a'\nregex = 'x'\n[allowlist]\nfoo = 1");
+        assert!(result.is_ok());
+        assert_eq!(warnings.len(), 1);
+    }
+}
@@ -0,0 +1,105 @@
+//! Checkpoint journal for `--journal`/`--resume`: every scanned image is
+//! appended as one JSON line as soon as it is done, so an interrupted scan
+//! can pick up where it stopped. Unlike the reports, records keep the OCR
+//! lines, which the SARIF output needs to locate findings.
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::Img;
+
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// Opens the journal at `path`. With `resume`, the images it already
+    /// holds are returned and new ones are appended; otherwise the journal
+    /// must not exist yet, so that forgetting `--resume` cannot wipe it.
+    pub fn open(path: &Path, resume: bool) -> Result<(Journal, Vec<Img>)> {
+        let mut done = Vec::new();
+        if resume {
+            match File::open(path) {
+                Ok(file) => done = read(path, file)?,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("Failed to read journal {}", path.display()))
+                }
+            }
+        } else if path.metadata().is_ok_and(|m| m.len() > 0) {
+            bail!(
+                "Journal {} already exists; pass --resume to continue that scan or delete it",
+                path.display()
+            );
+        }
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        // Holds OCR text, like the cache.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options
+            .open(path)
+            .with_context(|| format!("Failed to open journal {}", path.display()))?;
+        Ok((Journal { file }, done))
+    }
+
+    pub fn record(&mut self, img: &Img) -> Result<()> {
+        let mut record = serde_json::to_value(img)?;
+        record["lines"] = serde_json::to_value(&img.lines)?;
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        // One write per record, so an interruption can at worst leave a
+        // truncated last line.
+        self.file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+/// Reads every record; an image scanned again after a failure is only
+/// returned as it was last recorded.
+fn read(path: &Path, file: File) -> Result<Vec<Img>> {
+    let mut images: Vec<Img> = Vec::new();
+    let mut positions = HashMap::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read journal {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse(&line) {
+            Ok(img) => match positions.entry(img.path.clone()) {
+                Entry::Occupied(position) => images[*position.get()] = img,
+                Entry::Vacant(position) => {
+                    position.insert(images.len());
+                    images.push(img);
+                }
+            },
+            Err(err) => eprintln!(
+                "warning: {}:{}: ignoring unreadable journal record: {}",
+                path.display(),
+                index + 1,
+                err
+            ),
+        }
+    }
+    Ok(images)
+}
+
+fn parse(line: &str) -> Result<Img> {
+    let mut record: Value = serde_json::from_str(line)?;
+    let lines = record
+        .as_object_mut()
+        .and_then(|record| record.remove("lines"))
+        .unwrap_or_else(|| Value::Array(Vec::new()));
+    let mut img: Img = serde_json::from_value(record)?;
+    img.lines = serde_json::from_value(lines)?;
+    Ok(img)
+}
@@ -0,0 +1,602 @@
+//! Locating and loading the OCR models.
+//!
+//! An explicit `--detection-model`/`--recognition-model` path always wins.
+//! Builds with the `embedded-models` feature then use the copies baked into
+//! the binary. Otherwise each model is looked up by file name in, in order:
+//! `$EVILEYE_MODEL_DIR`, `$XDG_DATA_HOME/evileye/models`, each
+//! `$XDG_DATA_DIRS` entry's `evileye/models`, `$XDG_CACHE_HOME/evileye/models`
+//! and finally the current directory.
+//!
+//! `evileye models fetch|import` record each installed model's SHA-256 in a
+//! `SHA256SUMS` file next to it. A model that was looked up and does not
+//! match, or has no recorded checksum, is refused before it is handed to
+//! rten. Downloads from mirrors are checked against the checksum published
+//! next to each model, and downloads from the default location against the
+//! checksums pinned below.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use rten::Model;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const DETECTION_MODEL: &str = "text-detection.rten";
+pub const RECOGNITION_MODEL: &str = "text-recognition.rten";
+const MODELS: [&str; 2] = [DETECTION_MODEL, RECOGNITION_MODEL];
+
+const DEFAULT_BASE_URL: &str = "https://ocrs-models.s3-accelerate.amazonaws.com";
+const CHECKSUMS: &str = "SHA256SUMS";
+
+/// SHA-256 of each model as served from `DEFAULT_BASE_URL`. A model that is
+/// not listed here has its checksum recorded as downloaded.
+const PINNED_SHA256: &[(&str, &str)] = &[];
+
+/// Exit status of `models verify` when a model is missing or corrupt.
+const EXIT_VERIFY_FAILED: u8 = 2;
+
+#[cfg(feature = "embedded-models")]
+fn embedded(name: &str) -> Option<&'static [u8]> {
+    match name {
+        DETECTION_MODEL => Some(include_bytes!(env!("EVILEYE_EMBEDDED_DETECTION_MODEL"))),
+        RECOGNITION_MODEL => Some(include_bytes!(env!("EVILEYE_EMBEDDED_RECOGNITION_MODEL"))),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "embedded-models"))]
+fn embedded(_name: &str) -> Option<&'static [u8]> {
+    None
+}
+
+/// Loads the model called `name` and returns it with its SHA-256, which
+/// identifies the model in the OCR cache. `flag` is the command line option
+/// that sets `explicit`, used in error messages.
+pub fn load(name: &str, explicit: Option<&Path>, flag: &str) -> Result<(Model, String)> {
+    if explicit.is_none() {
+        if let Some(data) = embedded(name) {
+            let model = Model::load(data.to_vec())
+                .with_context(|| format!("Failed to load embedded {}", name))?;
+            return Ok((model, format!("{:x}", Sha256::digest(data))));
+        }
+    }
+    let path = locate(name, explicit, flag)?;
+    // The bytes that were checked are the ones loaded, even if the file is
+    // replaced meanwhile.
+    let data = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let sha256 = format!("{:x}", Sha256::digest(&data));
+    match check(&path, sha256.clone())? {
+        Integrity::Verified => {}
+        // A file passed explicitly is trusted as such.
+        Integrity::Unrecorded if explicit.is_some() => eprintln!(
+            "warning: no recorded checksum for {}; install it with `evileye models import` \
+             to have it verified",
+            path.display()
+        ),
+        // Anything else could have been dropped into a search directory,
+        // such as the current one.
+        Integrity::Unrecorded => bail!(
+            "{} has no recorded checksum. Install it with `evileye models fetch`, or with \
+             `evileye models import --sha256 <HEX>`, or pass it with {}",
+            path.display(),
+            flag
+        ),
+        Integrity::Mismatch { expected, actual } => bail!(
+            "{} is corrupt or was modified: SHA-256 is {} but {} was recorded. \
+             Reinstall it with `evileye models fetch --force`",
+            path.display(),
+            actual,
+            expected
+        ),
+    }
+    let model = Model::load(data).with_context(|| format!("Failed to load {}", path.display()))?;
+    Ok((model, sha256))
+}
+
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("EVILEYE_MODEL_DIR").filter(|d| !d.is_empty()) {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(dir) = data_home() {
+        dirs.push(dir.join("evileye").join("models"));
+    }
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    for dir in env::split_paths(&data_dirs).filter(|d| d.is_absolute()) {
+        dirs.push(dir.join("evileye").join("models"));
+    }
+    if let Some(dir) = cache_home() {
+        dirs.push(dir.join("evileye").join("models"));
+    }
+    if let Ok(dir) = env::current_dir() {
+        dirs.push(dir);
+    }
+    dirs
+}
+
+/// Where `evileye models` installs to: `$EVILEYE_MODEL_DIR` if set, else the
+/// XDG data directory.
+fn install_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("EVILEYE_MODEL_DIR").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    data_home()
+        .map(|d| d.join("evileye").join("models"))
+        .ok_or_else(|| anyhow!("Cannot determine a model directory; pass --dir or set HOME"))
+}
+
+fn data_home() -> Option<PathBuf> {
+    env_path("XDG_DATA_HOME").or_else(|| home().map(|h| h.join(".local").join("share")))
+}
+
+pub fn cache_home() -> Option<PathBuf> {
+    env_path("XDG_CACHE_HOME").or_else(|| home().map(|h| h.join(".cache")))
+}
+
+fn home() -> Option<PathBuf> {
+    env_path("HOME")
+}
+
+/// XDG variables must hold absolute paths; anything else is ignored.
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+/// Resolves the model called `name`, preferring `explicit` when given.
+fn locate(name: &str, explicit: Option<&Path>, flag: &str) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        if !path.is_file() {
+            bail!(
+                "{} {} does not exist or is not a file",
+                flag,
+                path.display()
+            );
+        }
+        return Ok(path.to_path_buf());
+    }
+
+    let dirs = search_dirs();
+    if let Some(path) = dirs.iter().map(|d| d.join(name)).find(|p| p.is_file()) {
+        return Ok(path);
+    }
+
+    let searched = dirs
+        .iter()
+        .map(|d| format!("  {}", d.display()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    bail!(
+        "Could not find the OCR model {}. Searched:\n{}\n\
+         Pass {} <FILE>, set EVILEYE_MODEL_DIR, or install the models with \
+         `evileye models fetch`.",
+        name,
+        searched,
+        flag
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Integrity {
+    Verified,
+    Unrecorded,
+    Mismatch { expected: String, actual: String },
+}
+
+fn integrity(path: &Path) -> Result<Integrity> {
+    check(path, sha256_file(path)?)
+}
+
+/// Compares `actual`, the SHA-256 of the model at `path`, with the one
+/// recorded for it.
+fn check(path: &Path, actual: String) -> Result<Integrity> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let Some(expected) = read_checksums(dir)?.remove(name.as_ref()) else {
+        return Ok(Integrity::Unrecorded);
+    };
+    Ok(if actual == expected {
+        Integrity::Verified
+    } else {
+        Integrity::Mismatch { expected, actual }
+    })
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Reads a `sha256sum`-style file: `<hex>  <file name>` per line.
+fn read_checksums(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let path = dir.join(CHECKSUMS);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            let (hash, name) = line.split_once(char::is_whitespace)?;
+            let name = name.trim_start().trim_start_matches('*');
+            Some((name.to_string(), hash.to_lowercase()))
+        })
+        .collect())
+}
+
+fn record_checksum(dir: &Path, name: &str, hash: &str) -> Result<()> {
+    let mut checksums = read_checksums(dir)?;
+    checksums.insert(name.to_string(), hash.to_string());
+    let contents = checksums
+        .iter()
+        .map(|(name, hash)| format!("{}  {}\n", hash, name))
+        .collect::<String>();
+    let path = dir.join(CHECKSUMS);
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub fn command() -> Command {
+    let dir = Arg::new("dir")
+        .long("dir")
+        .value_name("DIR")
+        .help("Model directory (default: $EVILEYE_MODEL_DIR or $XDG_DATA_HOME/evileye/models)")
+        .value_parser(clap::value_parser!(PathBuf));
+    Command::new("models")
+        .about("Install, list and verify the OCR models")
+        .after_help("To scan a directory named `models`, write it as `./models`.")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("fetch")
+                .about("Download the models and record their checksums")
+                .arg(
+                    Arg::new("base_url")
+                        .long("base-url")
+                        .value_name("URL")
+                        .env("EVILEYE_MODEL_BASE_URL")
+                        .default_value(DEFAULT_BASE_URL)
+                        .help("Where to download from; a mirror must serve the same file names"),
+                )
+                .arg(dir.clone())
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Download even if a verified copy is already installed")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Install a model from a local file")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("as")
+                        .long("as")
+                        .value_parser(["detection", "recognition"])
+                        .help("Which model the file is; guessed from the file name if omitted"),
+                )
+                .arg(
+                    Arg::new("sha256")
+                        .long("sha256")
+                        .value_name("HEX")
+                        .help("Refuse the file unless it has this SHA-256"),
+                )
+                .arg(dir.clone()),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Show where each model would be loaded from")
+                .arg(dir.clone()),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check installed models against their recorded checksums")
+                .arg(dir),
+        )
+}
+
+/// Runs `evileye models ...`, returning the process exit status.
+pub fn run(matches: &ArgMatches) -> Result<u8> {
+    let dir = |m: &ArgMatches| match m.get_one::<PathBuf>("dir") {
+        Some(dir) => Ok(dir.clone()),
+        None => install_dir(),
+    };
+    match matches.subcommand() {
+        Some(("fetch", m)) => {
+            let base_url = m.get_one::<String>("base_url").unwrap();
+            fetch(base_url, &dir(m)?, m.get_flag("force"))?;
+            Ok(0)
+        }
+        Some(("import", m)) => {
+            let file = m.get_one::<PathBuf>("file").unwrap();
+            let name = match m.get_one::<String>("as").map(String::as_str) {
+                Some("detection") => DETECTION_MODEL,
+                Some("recognition") => RECOGNITION_MODEL,
+                _ => guess_name(file)?,
+            };
+            import(file, name, m.get_one::<String>("sha256"), &dir(m)?)?;
+            Ok(0)
+        }
+        Some(("list", m)) => list(m.get_one::<PathBuf>("dir").map(PathBuf::as_path)),
+        Some(("verify", m)) => verify(m.get_one::<PathBuf>("dir").map(PathBuf::as_path)),
+        _ => unreachable!("subcommand_required"),
+    }
+}
+
+fn fetch(base_url: &str, dir: &Path, force: bool) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for name in MODELS {
+        let dest = dir.join(name);
+        if !force && dest.is_file() && integrity(&dest)? == Integrity::Verified {
+            println!("{} is already installed and verified", dest.display());
+            continue;
+        }
+
+        let url = format!("{}/{}", base_url.trim_end_matches('/'), name);
+        let expected = match pinned(base_url, name) {
+            Some(hash) => Some(hash.to_string()),
+            None if is_default(base_url) => {
+                eprintln!(
+                    "warning: this build has no pinned checksum for {}; its checksum is \
+                     recorded as downloaded",
+                    name
+                );
+                None
+            }
+            None => Some(published_checksum(&url)?),
+        };
+        eprintln!("Downloading {}", url);
+        let response = ureq::get(&url)
+            .call()
+            .with_context(|| format!("Failed to download {}", url))?;
+        let expected_len = response
+            .header("Content-Length")
+            .and_then(|len| len.parse::<u64>().ok());
+
+        let partial = dir.join(format!(".{}.partial", name));
+        let result = download(response.into_reader(), &partial, expected_len).and_then(|hash| {
+            if let Some(expected) = expected.filter(|expected| *expected != hash) {
+                bail!("{} has SHA-256 {} but {} was expected", url, hash, expected);
+            }
+            Ok(hash)
+        });
+        let hash = match result {
+            Ok(hash) => hash,
+            Err(err) => {
+                let _ = fs::remove_file(&partial);
+                return Err(err);
+            }
+        };
+
+        fs::rename(&partial, &dest)
+            .with_context(|| format!("Failed to install {}", dest.display()))?;
+        record_checksum(dir, name, &hash)?;
+        println!("Installed {} (sha256 {})", dest.display(), hash);
+    }
+    Ok(())
+}
+
+/// Streams `reader` into `path`, returning the SHA-256 of what was written.
+fn download(mut reader: impl Read, path: &Path, expected_len: Option<u64>) -> Result<String> {
+    let mut file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut written = 0u64;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n])?;
+        written += n as u64;
+    }
+    file.sync_all()?;
+    if let Some(expected) = expected_len {
+        if written != expected {
+            bail!("Download truncated: got {} of {} bytes", written, expected);
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The pinned checksum of `name`, if it is fetched from the default
+/// location.
+fn pinned(base_url: &str, name: &str) -> Option<&'static str> {
+    if !is_default(base_url) {
+        return None;
+    }
+    PINNED_SHA256
+        .iter()
+        .find(|(pinned, _)| *pinned == name)
+        .map(|(_, hash)| *hash)
+}
+
+fn is_default(base_url: &str) -> bool {
+    base_url.trim_end_matches('/') == DEFAULT_BASE_URL
+}
+
+/// Mirrors must publish `<model>.sha256` next to each model.
+fn published_checksum(url: &str) -> Result<String> {
+    let url = format!("{}.sha256", url);
+    let response = match ureq::get(&url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404 | 403, _)) => bail!(
+            "No checksum to verify the download against: {} does not exist. \
+             Download the model yourself and install it with \
+             `evileye models import --sha256 <HEX>`",
+            url
+        ),
+        Err(err) => return Err(err).with_context(|| format!("Failed to download {}", url)),
+    };
+    let body = response.into_string()?;
+    let hash = body
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("{} is empty", url))?;
+    Ok(hash.to_lowercase())
+}
+
+fn guess_name(file: &Path) -> Result<&'static str> {
+    let name = file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    match (name.contains("detection"), name.contains("recognition")) {
+        (true, false) => Ok(DETECTION_MODEL),
+        (false, true) => Ok(RECOGNITION_MODEL),
+        _ => bail!(
+            "Cannot tell which model {} is; pass --as detection or --as recognition",
+            file.display()
+        ),
+    }
+}
+
+fn import(file: &Path, name: &str, expected: Option<&String>, dir: &Path) -> Result<()> {
+    let hash = sha256_file(file)?;
+    if let Some(expected) = expected {
+        if !expected.eq_ignore_ascii_case(&hash) {
+            bail!(
+                "{} has SHA-256 {}, expected {}",
+                file.display(),
+                hash,
+                expected
+            );
+        }
+    }
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let dest = dir.join(name);
+    let partial = dir.join(format!(".{}.partial", name));
+    fs::copy(file, &partial).with_context(|| format!("Failed to copy {}", file.display()))?;
+    fs::rename(&partial, &dest).with_context(|| format!("Failed to install {}", dest.display()))?;
+    record_checksum(dir, name, &hash)?;
+    println!("Installed {} (sha256 {})", dest.display(), hash);
+    Ok(())
+}
+
+/// The model files `list` and `verify` look at: the ones in `dir` if given,
+/// otherwise the ones a scan would pick up.
+fn installed(dir: Option<&Path>) -> Vec<(&'static str, Option<PathBuf>)> {
+    MODELS
+        .iter()
+        .map(|&name| {
+            let path = match dir {
+                Some(dir) => Some(dir.join(name)).filter(|p| p.is_file()),
+                None => search_dirs()
+                    .into_iter()
+                    .map(|d| d.join(name))
+                    .find(|p| p.is_file()),
+            };
+            (name, path)
+        })
+        .collect()
+}
+
+fn list(dir: Option<&Path>) -> Result<u8> {
+    for (name, path) in installed(dir) {
+        if dir.is_none() && embedded(name).is_some() {
+            println!("{}: embedded in the binary", name);
+            continue;
+        }
+        let Some(path) = path else {
+            println!("{}: not installed", name);
+            continue;
+        };
+        let size = fs::metadata(&path)?.len();
+        let status = match integrity(&path)? {
+            Integrity::Verified => "checksum ok",
+            Integrity::Unrecorded => "no recorded checksum",
+            Integrity::Mismatch { .. } => "CHECKSUM MISMATCH",
+        };
+        println!("{}: {} ({} bytes, {})", name, path.display(), size, status);
+    }
+    Ok(0)
+}
+
+fn verify(dir: Option<&Path>) -> Result<u8> {
+    let mut ok = true;
+    for (name, path) in installed(dir) {
+        if dir.is_none() && embedded(name).is_some() {
+            println!("{}: embedded in the binary", name);
+            continue;
+        }
+        let Some(path) = path else {
+            println!("{}: not installed", name);
+            ok = false;
+            continue;
+        };
+        match integrity(&path)? {
+            Integrity::Verified => println!("{}: ok", path.display()),
+            Integrity::Unrecorded => {
+                println!("{}: no recorded checksum", path.display());
+                ok = false;
+            }
+            Integrity::Mismatch { expected, actual } => {
+                println!(
+                    "{}: MISMATCH (expected {}, got {})",
+                    path.display(),
+                    expected,
+                    actual
+                );
+                ok = false;
+            }
+        }
+    }
+    Ok(if ok { 0 } else { EXIT_VERIFY_FAILED })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_only_for_the_default_location() {
+        for name in MODELS {
+            let default = format!("{}/", DEFAULT_BASE_URL);
+            assert_eq!(pinned(&default, name), pinned(DEFAULT_BASE_URL, name));
+            assert_eq!(pinned("https://mirror.example", name), None);
+        }
+        assert_eq!(pinned(DEFAULT_BASE_URL, "other.rten"), None);
+    }
+
+    #[test]
+    fn checksums() {
+        let dir = env::temp_dir().join(format!("evileye-models-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let model = dir.join(DETECTION_MODEL);
+        fs::write(&model, b"model").unwrap();
+        let hash = format!("{:x}", Sha256::digest(b"model"));
+
+        let unrecorded = integrity(&model).unwrap();
+        record_checksum(&dir, DETECTION_MODEL, &hash).unwrap();
+        let verified = integrity(&model).unwrap();
+        let mismatch = check(&model, "0".repeat(64)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(unrecorded, Integrity::Unrecorded);
+        assert_eq!(verified, Integrity::Verified);
+        assert_eq!(
+            mismatch,
+            Integrity::Mismatch {
+                expected: hash,
+                actual: "0".repeat(64)
+            }
+        );
+    }
+}
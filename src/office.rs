@@ -0,0 +1,399 @@
+//! Office Open XML (docx, pptx, xlsx) and OpenDocument (odt, odp, ods, odg)
+//! files: both are zip archives that keep pasted screenshots as separate
+//! image files, which are extracted and scanned like image files.
+//!
+//! Embedded images are reported as `deck.pptx!/ppt/media/image7.png`, after
+//! their path inside the archive. Where the document says which slide,
+//! sheet or page shows an image, that is reported as well; text documents
+//! have no fixed pages, so their images only get the path.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::archive::{self, Budget};
+use crate::discovery::{self, Input, Item};
+use crate::{ErrorKind, Placement, ScanError, Skipped};
+
+/// Extensions of the document formats handled here, including macro-enabled
+/// variants and templates.
+const EXTENSIONS: [&str; 22] = [
+    "docm", "docx", "dotm", "dotx", "potm", "potx", "ppsm", "ppsx", "pptm", "pptx", "xlsm", "xlsx",
+    "xltm", "xltx", "odg", "odp", "ods", "odt", "otg", "otp", "ots", "ott",
+];
+
+/// Offset of the first member's name in a zip archive.
+const FIRST_NAME_OFFSET: usize = 30;
+
+/// Where both formats keep embedded images.
+const MEDIA_DIRS: [&str; 4] = ["word/media/", "ppt/media/", "xl/media/", "Pictures/"];
+
+pub fn has_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Recognizes documents without a known extension by their first member,
+/// which both formats require to be the content type list or the MIME type.
+pub fn is_office(header: &[u8]) -> bool {
+    header.starts_with(archive::ZIP_MAGIC)
+        && header.get(FIRST_NAME_OFFSET..).is_some_and(|name| {
+            name.starts_with(b"[Content_Types].xml") || name.starts_with(b"mimetype")
+        })
+}
+
+/// Hands every embedded image of the document at `path` to `emit`, stopping
+/// early once it returns false. Images whose width and height are both below
+/// `min_dimension` are left out, as are vector graphics (EMF, WMF, SVG),
+/// which would need rendering first. Every member and everything
+/// decompressed is charged to `budget`. Returns false if stopped early.
+pub fn extract(
+    path: &Path,
+    bytes: &[u8],
+    min_dimension: Option<u32>,
+    budget: &mut Budget,
+    emit: &mut dyn FnMut(Item) -> bool,
+) -> Result<bool, archive::Error> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let placements = if archive.index_for_name("mimetype").is_some() {
+        odf_placements(&mut archive, budget)
+    } else {
+        ooxml_placements(&mut archive, budget)
+    };
+    let document = path.to_string_lossy();
+
+    for index in 0..archive.len() {
+        budget.enter()?;
+        let mut member = archive.by_index(index)?;
+        let Ok(name) = member.name().map(|name| name.into_owned()) else {
+            continue;
+        };
+        if member.is_dir() || !MEDIA_DIRS.iter().any(|dir| name.starts_with(dir)) {
+            continue;
+        }
+        let member_path = format!("{}!/{}", document, name);
+        let placement = placements.get(&name).copied();
+        let item = match budget.read(Vec::new(), &mut member) {
+            Ok(bytes) => {
+                let Ok(format) = image::guess_format(&bytes) else {
+                    continue;
+                };
+                if !format.reading_enabled() || discovery::too_small(&bytes, min_dimension) {
+                    continue;
+                }
+                Item::Input(Input::Bytes {
+                    name: member_path,
+                    bytes,
+                    placement,
+                })
+            }
+            Err(archive::Error::Unreadable(message)) => Item::Skipped(Skipped {
+                path: member_path,
+                error: ScanError {
+                    kind: ErrorKind::Decode,
+                    message,
+                },
+            }),
+            Err(err) => return Err(err),
+        };
+        if !emit(item) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Maps images to the first slide or sheet showing them, following the
+/// relationships from the presentation or workbook in its own order.
+fn ooxml_placements<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &mut Budget,
+) -> HashMap<String, Placement> {
+    let (main, element, placement): (_, _, fn(usize) -> Placement) =
+        if archive.index_for_name("ppt/presentation.xml").is_some() {
+            ("ppt/presentation.xml", "p:sldId", Placement::Slide)
+        } else if archive.index_for_name("xl/workbook.xml").is_some() {
+            ("xl/workbook.xml", "sheet", Placement::Sheet)
+        } else {
+            return HashMap::new();
+        };
+    let parts = relationships(archive, main, budget);
+    let Some(xml) = read_text(archive, main, budget) else {
+        return HashMap::new();
+    };
+
+    let mut placements = HashMap::new();
+    let ids = start_tags(&xml, element)
+        .into_iter()
+        .filter_map(|at| attribute(attributes(&xml, at), "r:id"));
+    for (number, id) in ids.enumerate() {
+        let Some((_, part)) = parts.get(&id) else {
+            continue;
+        };
+        // Slides link their images directly, sheets through a drawing.
+        for (kind, target) in relationships(archive, part, budget).into_values() {
+            let images = if kind.ends_with("/drawing") {
+                relationships(archive, &target, budget)
+                    .into_values()
+                    .collect()
+            } else {
+                vec![(kind, target)]
+            };
+            for (_, image) in images
+                .into_iter()
+                .filter(|(kind, _)| kind.ends_with("/image"))
+            {
+                placements
+                    .entry(image)
+                    .or_insert_with(|| placement(number + 1));
+            }
+        }
+    }
+    placements
+}
+
+/// The internal relationships of `part`, by id, as `(type, target)` with
+/// the target resolved to a member name.
+fn relationships<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    part: &str,
+    budget: &mut Budget,
+) -> HashMap<String, (String, String)> {
+    let (dir, file) = part.rsplit_once('/').unwrap_or(("", part));
+    let rels = match dir {
+        "" => format!("_rels/{}.rels", file),
+        dir => format!("{}/_rels/{}.rels", dir, file),
+    };
+    let Some(xml) = read_text(archive, &rels, budget) else {
+        return HashMap::new();
+    };
+    start_tags(&xml, "Relationship")
+        .into_iter()
+        .map(|at| attributes(&xml, at))
+        .filter(|tag| attribute(tag, "TargetMode").is_none_or(|mode| mode != "External"))
+        .filter_map(|tag| {
+            let target = attribute(tag, "Target")?;
+            Some((
+                attribute(tag, "Id")?,
+                (attribute(tag, "Type")?, resolve(dir, &target)),
+            ))
+        })
+        .collect()
+}
+
+/// Resolves a relationship target relative to the directory of its source.
+fn resolve(dir: &str, target: &str) -> String {
+    let mut segments: Vec<&str> = match target.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => dir.split('/').filter(|s| !s.is_empty()).collect(),
+    };
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Maps images to the first slide, sheet or drawing page showing them, by
+/// their position in `content.xml`.
+fn odf_placements<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    budget: &mut Budget,
+) -> HashMap<String, Placement> {
+    let mimetype = read_text(archive, "mimetype", budget).unwrap_or_default();
+    let (element, placement): (_, fn(usize) -> Placement) = match mimetype
+        .trim()
+        .strip_prefix("application/vnd.oasis.opendocument.")
+        .map(|kind| kind.trim_end_matches("-template"))
+    {
+        Some("presentation") => ("draw:page", Placement::Slide),
+        Some("spreadsheet") => ("table:table", Placement::Sheet),
+        Some("graphics") => ("draw:page", Placement::Page),
+        _ => return HashMap::new(),
+    };
+    let Some(xml) = read_text(archive, "content.xml", budget) else {
+        return HashMap::new();
+    };
+
+    let mut placements = HashMap::new();
+    let mut pages = start_tags(&xml, element).into_iter().peekable();
+    let mut number = 0;
+    for (at, _) in xml.match_indices("xlink:href=\"") {
+        while pages.next_if(|&page| page < at).is_some() {
+            number += 1;
+        }
+        let href = &xml[at + "xlink:href=\"".len()..];
+        let Some(href) = href.split('"').next() else {
+            continue;
+        };
+        let href = unescape(href.trim_start_matches("./"));
+        if number > 0 && href.starts_with("Pictures/") {
+            placements.entry(href).or_insert_with(|| placement(number));
+        }
+    }
+    placements
+}
+
+/// Reads a part of the document, charging it to `budget`. A part that
+/// exhausts the budget is left out; the next member fails instead.
+fn read_text<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+    budget: &mut Budget,
+) -> Option<String> {
+    let mut member = archive.by_name(name).ok()?;
+    let bytes = budget.read(Vec::new(), &mut member).ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// The offset just past `<name` of every start tag named `name`. This is
+/// nowhere near a real XML parser, but the parts read here are
+/// machine-written and flat.
+fn start_tags(xml: &str, name: &str) -> Vec<usize> {
+    let open = format!("<{}", name);
+    xml.match_indices(&open)
+        .map(|(at, _)| at + open.len())
+        .filter(|&end| is_tag_end(xml[end..].chars().next()))
+        .collect()
+}
+
+/// The attributes of the start tag whose name ends at `at`.
+fn attributes(xml: &str, at: usize) -> &str {
+    let rest = &xml[at..];
+    rest.split('>').next().unwrap_or(rest)
+}
+
+fn is_tag_end(next: Option<char>) -> bool {
+    next.is_some_and(|c| c.is_whitespace() || c == '>' || c == '/')
+}
+
+/// The unescaped value of attribute `name` in the attribute text of a tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    loop {
+        let at = rest.find(name)?;
+        let preceded_by_space = rest[..at].chars().last().is_none_or(char::is_whitespace);
+        rest = &rest[at + name.len()..];
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+        let value = &value[1..];
+        let end = value.find(quote)?;
+        if preceded_by_space {
+            return Some(unescape(&value[..end]));
+        }
+        rest = &value[end..];
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::Limits;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn attributes_in_any_order() {
+        let xml =
+            r#"<Relationship TargetMode="External" Target="https://example.com/a.png" Id="rId1"/>"#;
+        let tag = attributes(xml, start_tags(xml, "Relationship")[0]);
+        assert_eq!(attribute(tag, "TargetMode").as_deref(), Some("External"));
+        assert_eq!(
+            attribute(tag, "Target").as_deref(),
+            Some("https://example.com/a.png")
+        );
+        assert_eq!(attribute(tag, "Id").as_deref(), Some("rId1"));
+        assert_eq!(attribute(tag, "Type"), None);
+    }
+
+    #[test]
+    fn single_quoted_and_escaped_attributes() {
+        let tag = r#" Id='rId2' Target = 'media/a&amp;b.png' Type="x""#;
+        assert_eq!(attribute(tag, "Id").as_deref(), Some("rId2"));
+        assert_eq!(attribute(tag, "Target").as_deref(), Some("media/a&b.png"));
+    }
+
+    #[test]
+    fn attribute_names_are_whole_words() {
+        let tag = r#" r:id="rId3" id="4""#;
+        assert_eq!(attribute(tag, "id").as_deref(), Some("4"));
+        assert_eq!(attribute(tag, "r:id").as_deref(), Some("rId3"));
+    }
+
+    #[test]
+    fn start_tags_are_whole_names() {
+        let xml = "<sheets><sheet name='a'/><sheetView/><sheet>";
+        assert_eq!(start_tags(xml, "sheet").len(), 2);
+        assert_eq!(start_tags(xml, "sheets").len(), 1);
+    }
+
+    #[test]
+    fn targets() {
+        assert_eq!(
+            resolve("ppt/slides", "../media/image1.png"),
+            "ppt/media/image1.png"
+        );
+        assert_eq!(
+            resolve("xl/drawings", "../../xl/media/a.png"),
+            "xl/media/a.png"
+        );
+        assert_eq!(
+            resolve("ppt/slides", "/ppt/media/image1.png"),
+            "ppt/media/image1.png"
+        );
+        assert_eq!(resolve("word", "./media/a.png"), "word/media/a.png");
+        assert_eq!(resolve("", "ppt/presentation.xml"), "ppt/presentation.xml");
+        // Cannot climb out of the archive.
+        assert_eq!(resolve("word", "../../../a.png"), "a.png");
+    }
+
+    #[test]
+    fn external_relationships_are_left_out() {
+        let rels = r#"<Relationships>
+            <Relationship TargetMode="External" Id="rId1" Type=".../image" Target="https://example.com/a.png"/>
+            <Relationship Id='rId2' Type='.../image' Target='../media/image2.png'/>
+            <Relationship Target="/ppt/media/image3.png" Type=".../image" Id="rId3"/>
+        </Relationships>"#;
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "ppt/slides/_rels/slide1.xml.rels",
+                SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(rels.as_bytes()).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut budget = Budget::new(Limits {
+            max_depth: 1,
+            max_size: 1 << 20,
+            max_entries: 10,
+        });
+        let parts = relationships(&mut archive, "ppt/slides/slide1.xml", &mut budget);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts["rId2"].1, "ppt/media/image2.png");
+        assert_eq!(parts["rId3"].1, "ppt/media/image3.png");
+    }
+}
@@ -0,0 +1,221 @@
+//! Multi-page TIFFs, such as fax and scanner exports. The `image` crate only
+//! decodes the first page, so these are read with the `tiff` crate directly,
+//! one page at a time. Single-page TIFFs are read here as well, since the
+//! `image` crate decodes neither bilevel nor fax images.
+
+use image::error::{DecodingError, UnsupportedError, UnsupportedErrorKind};
+use image::{
+    DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, ImageError, ImageFormat, ImageResult,
+    Luma, LumaA, Rgb, RgbImage, Rgba, RgbaImage,
+};
+use std::io::Cursor;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::{ColorType, TiffError};
+
+/// `NewSubfileType` bit marking a reduced-resolution copy of another page.
+const REDUCED_RESOLUTION: u32 = 1;
+
+/// A TIFF with more than one image, kept encoded so that its pages can be
+/// decoded one at a time.
+pub struct Document {
+    bytes: Vec<u8>,
+    dimensions: (u32, u32),
+}
+
+pub struct Page {
+    /// Position in the document, not counting thumbnails.
+    pub index: usize,
+    pub pixels: RgbImage,
+}
+
+/// Whether the TIFF in `bytes` holds more than one image. Some of them may
+/// turn out to be thumbnails.
+pub fn is_multi_page(bytes: &[u8]) -> bool {
+    Decoder::new(Cursor::new(bytes)).is_ok_and(|decoder| decoder.more_images())
+}
+
+/// Decodes a TIFF that is not `is_multi_page`.
+pub fn decode_single(bytes: &[u8]) -> ImageResult<RgbImage> {
+    let mut decoder = Decoder::new(Cursor::new(bytes)).map_err(image_error)?;
+    read_page(&mut decoder)
+}
+
+impl Document {
+    /// Reads the header of a TIFF that `is_multi_page`.
+    pub fn new(bytes: Vec<u8>) -> ImageResult<Document> {
+        let dimensions = Decoder::new(Cursor::new(bytes.as_slice()))
+            .and_then(|mut decoder| decoder.dimensions())
+            .map_err(image_error)?;
+        Ok(Document { bytes, dimensions })
+    }
+
+    /// The size of the first page.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    pub fn pages(&self) -> ImageResult<Pages<'_>> {
+        Ok(Pages {
+            decoder: Some(Decoder::new(Cursor::new(self.bytes.as_slice())).map_err(image_error)?),
+            started: false,
+            index: 0,
+        })
+    }
+
+    /// Decodes just the page at `index`, for rendering a thumbnail.
+    pub fn page(&self, index: usize) -> ImageResult<Option<RgbImage>> {
+        self.pages()?
+            .nth(index)
+            .transpose()
+            .map(|page| page.map(|page| page.pixels))
+    }
+}
+
+/// Decodes every page in turn, skipping thumbnails. A page that fails to
+/// decode is followed by the next one; only a broken directory of pages ends
+/// the document.
+pub struct Pages<'a> {
+    decoder: Option<Decoder<Cursor<&'a [u8]>>>,
+    started: bool,
+    index: usize,
+}
+
+impl Pages<'_> {
+    /// How many pages are left, going by their directory entries alone, so
+    /// without decoding any of them.
+    pub fn count_rest(mut self) -> usize {
+        let mut count = 0;
+        while let Some(Ok(())) = self.advance() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Moves on to the next page that is not a thumbnail.
+    fn advance(&mut self) -> Option<ImageResult<()>> {
+        loop {
+            let decoder = self.decoder.as_mut()?;
+            if self.started {
+                if !decoder.more_images() {
+                    self.decoder = None;
+                    return None;
+                }
+                if let Err(err) = decoder.next_image() {
+                    self.decoder = None;
+                    return Some(Err(image_error(err)));
+                }
+            }
+            self.started = true;
+
+            let subfile_type = decoder
+                .find_tag_unsigned::<u32>(Tag::NewSubfileType)
+                .ok()
+                .flatten()
+                .unwrap_or(0);
+            if subfile_type & REDUCED_RESOLUTION == 0 {
+                self.index += 1;
+                return Some(Ok(()));
+            }
+        }
+    }
+}
+
+impl Iterator for Pages<'_> {
+    type Item = ImageResult<Page>;
+
+    fn next(&mut self) -> Option<ImageResult<Page>> {
+        if let Err(err) = self.advance()? {
+            return Some(Err(err));
+        }
+        let decoder = self.decoder.as_mut()?;
+        Some(read_page(decoder).map(|pixels| Page {
+            index: self.index - 1,
+            pixels,
+        }))
+    }
+}
+
+/// Decodes the current page, in the color types `image` supports plus
+/// bilevel, which most scanned documents use.
+fn read_page(decoder: &mut Decoder<Cursor<&[u8]>>) -> ImageResult<RgbImage> {
+    let (width, height) = decoder.dimensions().map_err(image_error)?;
+    let color_type = decoder.colortype().map_err(image_error)?;
+    let data = decoder.read_image().map_err(image_error)?;
+    let image: Option<DynamicImage> = match (color_type, data) {
+        (ColorType::Gray(1), DecodingResult::U8(packed)) => {
+            let row_bytes = (width as usize).div_ceil(8);
+            let pixels = packed
+                .chunks(row_bytes)
+                .flat_map(|row| {
+                    (0..width as usize).map(move |x| {
+                        let bit = row.get(x / 8).map_or(0, |byte| byte >> (7 - x % 8) & 1);
+                        bit * 255
+                    })
+                })
+                .collect();
+            GrayImage::from_raw(width, height, pixels).map(Into::into)
+        }
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            GrayImage::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            GrayAlphaImage::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::GrayA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            RgbImage::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::RGB(16), DecodingResult::U16(data)) => {
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            RgbaImage::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, data).map(Into::into)
+        }
+        (ColorType::CMYK(8), DecodingResult::U8(data)) => {
+            let rgb = data
+                .chunks_exact(4)
+                .flat_map(|cmyk| {
+                    let k = 255 - cmyk[3] as u16;
+                    [0, 1, 2].map(|i| ((255 - cmyk[i] as u16) * k / 255) as u8)
+                })
+                .collect();
+            RgbImage::from_raw(width, height, rgb).map(Into::into)
+        }
+        (color_type, _) => {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormat::Tiff.into(),
+                    UnsupportedErrorKind::GenericFeature(format!("{:?} pages", color_type)),
+                ),
+            ))
+        }
+    };
+    image.map(|image| image.into_rgb8()).ok_or_else(|| {
+        ImageError::Decoding(DecodingError::new(
+            ImageFormat::Tiff.into(),
+            "page data does not match its dimensions",
+        ))
+    })
+}
+
+fn image_error(err: TiffError) -> ImageError {
+    match err {
+        TiffError::IoError(err) => ImageError::IoError(err),
+        TiffError::UnsupportedError(err) => {
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormat::Tiff.into(),
+                UnsupportedErrorKind::GenericFeature(err.to_string()),
+            ))
+        }
+        err => ImageError::Decoding(DecodingError::new(ImageFormat::Tiff.into(), err)),
+    }
+}
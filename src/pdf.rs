@@ -0,0 +1,504 @@
+//! PDFs: the raster images on each page are extracted and scanned like image
+//! files, and the text layer is checked with the secret rules directly.
+//!
+//! Embedded images are reported as `doc.pdf!/p3/Im0`, after the page they
+//! first appear on (counting from 1) and their resource name; images inside
+//! form XObjects get the form's name as well (`doc.pdf!/p3/Fm0/Im1`). The
+//! text layer is reported as `doc.pdf` itself, with the page of every line.
+
+use lopdf::{Dictionary, Document, LoadOptions, Object, ObjectId, Stream};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::archive::{self, Budget};
+use crate::discovery::{Input, Item};
+use crate::{BoundingBox, ErrorKind, OcrLine, ScanError, Skipped};
+
+/// The most any one stream may decompress to. PDFs are often downloaded
+/// from who knows where, and a few kilobytes of Flate can inflate to
+/// gigabytes.
+const MAX_STREAM_SIZE: usize = 256 << 20;
+
+/// The most pixels an image may have, so that a small stream of 1-bit
+/// samples cannot make for a PPM of gigabytes.
+const MAX_PIXELS: u64 = 64 << 20;
+
+/// Form XObjects can nest (and, in broken files, loop); deeper forms are
+/// ignored.
+const MAX_FORM_DEPTH: usize = 8;
+
+pub fn is_pdf(header: &[u8]) -> bool {
+    header.starts_with(b"%PDF-")
+}
+
+/// Hands every image and the text layer of the PDF at `path` to `emit`,
+/// stopping early once it returns false. Images whose width and height are
+/// both below `min_dimension` are left out. Every page and image, and
+/// everything decompressed, is charged to `budget`. Returns false if stopped
+/// early.
+pub fn extract(
+    path: &Path,
+    bytes: &[u8],
+    min_dimension: Option<u32>,
+    budget: &mut Budget,
+    emit: &mut dyn FnMut(Item) -> bool,
+) -> Result<bool, archive::Error> {
+    let doc = Document::load_mem_with_options(
+        bytes,
+        LoadOptions {
+            max_decompressed_size: Some(stream_limit(budget)),
+            ..Default::default()
+        },
+    )
+    .map_err(|err| archive::Error::Unreadable(err.to_string()))?;
+    let name = path.to_string_lossy();
+    let mut extracted = HashSet::new();
+    let mut lines = Vec::new();
+
+    for (number, page_id) in doc.get_pages() {
+        budget.enter()?;
+        let page = format!("{}!/p{}", name, number);
+        match doc.extract_text_with_limit(&[number], stream_limit(budget)) {
+            Ok(text) => {
+                budget.charge(text.len())?;
+                lines.extend(
+                    text.lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .enumerate()
+                        .map(|(index, line)| OcrLine {
+                            text: line.to_string(),
+                            bbox: BoundingBox {
+                                x: 0,
+                                y: 0,
+                                width: 0,
+                                height: 0,
+                            },
+                            frame: None,
+                            timestamp_ms: None,
+                            page: Some(number as usize - 1),
+                            local_line: Some(index),
+                        }),
+                )
+            }
+            Err(err) => {
+                let error = format!("could not extract the text layer: {}", err);
+                if !emit(skipped(page.clone(), ErrorKind::Decode, error)) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        let mut images = Vec::new();
+        let (own, inherited) = match doc.get_page_resources(page_id) {
+            Ok(resources) => resources,
+            Err(err) => {
+                let error = format!("could not read the page resources: {}", err);
+                if !emit(skipped(page, ErrorKind::Decode, error)) {
+                    return Ok(false);
+                }
+                continue;
+            }
+        };
+        for resources in own.into_iter().chain(
+            inherited
+                .iter()
+                .filter_map(|&id| doc.get_dictionary(id).ok()),
+        ) {
+            collect_images(&doc, resources, &page, 0, &mut images);
+        }
+        for (member, id, stream) in images {
+            if !extracted.insert(id) {
+                continue;
+            }
+            budget.enter()?;
+            let item = match image_bytes(&doc, stream, min_dimension, budget) {
+                Ok(Some(bytes)) => Item::Input(Input::Bytes {
+                    name: member,
+                    bytes,
+                    placement: None,
+                }),
+                Ok(None) => continue,
+                Err(error) if error.kind == ErrorKind::Limit => {
+                    return Err(archive::Error::Limit(error.message));
+                }
+                Err(error) => Item::Skipped(Skipped {
+                    path: member,
+                    error,
+                }),
+            };
+            if !emit(item) {
+                return Ok(false);
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        return Ok(true);
+    }
+    Ok(emit(Item::Input(Input::Text {
+        name: name.to_string(),
+        lines,
+    })))
+}
+
+/// The most the next stream may decompress to.
+fn stream_limit(budget: &Budget) -> usize {
+    budget.left().min(MAX_STREAM_SIZE as u64) as usize
+}
+
+fn skipped(path: String, kind: ErrorKind, message: String) -> Item {
+    Item::Skipped(Skipped {
+        path,
+        error: ScanError { kind, message },
+    })
+}
+
+/// Collects the image XObjects in `resources` as `(path, id, stream)`,
+/// descending into form XObjects.
+fn collect_images<'a>(
+    doc: &'a Document,
+    resources: &'a Dictionary,
+    prefix: &str,
+    depth: usize,
+    images: &mut Vec<(String, ObjectId, &'a Stream)>,
+) {
+    let Some(xobjects) = resources
+        .get(b"XObject")
+        .ok()
+        .and_then(|object| doc.dereference(object).ok())
+        .and_then(|(_, object)| object.as_dict().ok())
+    else {
+        return;
+    };
+    for (key, value) in xobjects.iter() {
+        let Ok(id) = value.as_reference() else {
+            continue;
+        };
+        let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) else {
+            continue;
+        };
+        let path = format!("{}/{}", prefix, String::from_utf8_lossy(key));
+        match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+            Ok(b"Image") => images.push((path, id, stream)),
+            Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                if let Some(resources) = stream
+                    .dict
+                    .get(b"Resources")
+                    .ok()
+                    .and_then(|object| doc.dereference(object).ok())
+                    .and_then(|(_, object)| object.as_dict().ok())
+                {
+                    collect_images(doc, resources, &path, depth + 1, images);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Turns an image XObject into bytes the image decoders understand: JPEGs
+/// are passed through, raw samples are wrapped in a PPM header. Returns
+/// `None` for images not worth scanning (masks, and ones below
+/// `min_dimension`). Fails with `ErrorKind::Limit` once `budget` runs out.
+fn image_bytes(
+    doc: &Document,
+    stream: &Stream,
+    min_dimension: Option<u32>,
+    budget: &mut Budget,
+) -> Result<Option<Vec<u8>>, ScanError> {
+    let dict = &stream.dict;
+    let number = |key: &[u8]| dict.get(key).and_then(Object::as_i64).ok();
+    if dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false)
+    {
+        return Ok(None);
+    }
+    let (Some(width), Some(height)) = (number(b"Width"), number(b"Height")) else {
+        return Err(decode_error("image without a size"));
+    };
+    let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+        return Err(decode_error("image with an invalid size"));
+    };
+    if min_dimension.is_some_and(|min| width < min && height < min) {
+        return Ok(None);
+    }
+
+    let filters = stream.filters().unwrap_or_default();
+    match filters.as_slice() {
+        [b"DCTDecode"] => {
+            charge(budget, stream.content.len())?;
+            return Ok(Some(stream.content.clone()));
+        }
+        [.., b"JPXDecode"] => return Err(unsupported("JPEG 2000 images")),
+        [.., b"CCITTFaxDecode"] => return Err(unsupported("CCITT fax images")),
+        [.., b"JBIG2Decode"] => return Err(unsupported("JBIG2 images")),
+        [.., b"DCTDecode"] => return Err(unsupported("filtered JPEG images")),
+        _ => {}
+    }
+    let samples = stream
+        .get_plain_content_with_limit(stream_limit(budget))
+        .map_err(|err| decode_error(&err.to_string()))?;
+    charge(budget, samples.len())?;
+    let bits = number(b"BitsPerComponent").unwrap_or(8);
+    let color_space = dict
+        .get(b"ColorSpace")
+        .map_err(|_| decode_error("image without a color space"))
+        .and_then(|object| ColorSpace::parse(doc, object, 0))?;
+    to_ppm(width, height, bits, &color_space, &samples).map(Some)
+}
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// A palette of colors in the base color space.
+    Indexed(Box<ColorSpace>, Vec<u8>),
+}
+
+impl ColorSpace {
+    fn parse(doc: &Document, object: &Object, depth: usize) -> Result<ColorSpace, ScanError> {
+        let (_, object) = doc
+            .dereference(object)
+            .map_err(|err| decode_error(&err.to_string()))?;
+        if let Ok(name) = object.as_name() {
+            return ColorSpace::from_name(name);
+        }
+        let array = object
+            .as_array()
+            .map_err(|_| decode_error("invalid color space"))?;
+        let family = array
+            .first()
+            .and_then(|family| family.as_name().ok())
+            .unwrap_or_default();
+        match family {
+            b"ICCBased" => {
+                let components = array
+                    .get(1)
+                    .and_then(|profile| doc.dereference(profile).ok())
+                    .and_then(|(_, profile)| profile.as_stream().ok())
+                    .and_then(|profile| profile.dict.get(b"N").and_then(Object::as_i64).ok());
+                match components {
+                    Some(1) => Ok(ColorSpace::Gray),
+                    Some(3) => Ok(ColorSpace::Rgb),
+                    Some(4) => Ok(ColorSpace::Cmyk),
+                    _ => Err(unsupported("ICC color spaces of this kind")),
+                }
+            }
+            b"Indexed" | b"I" if depth == 0 => {
+                let base = array
+                    .get(1)
+                    .ok_or_else(|| decode_error("indexed color space without a base"))?;
+                let base = ColorSpace::parse(doc, base, depth + 1)?;
+                let lookup = array
+                    .get(3)
+                    .and_then(|lookup| doc.dereference(lookup).ok())
+                    .and_then(|(_, lookup)| match lookup {
+                        Object::String(bytes, _) => Some(bytes.clone()),
+                        Object::Stream(stream) => {
+                            stream.get_plain_content_with_limit(MAX_STREAM_SIZE).ok()
+                        }
+                        _ => None,
+                    })
+                    .ok_or_else(|| decode_error("indexed color space without a palette"))?;
+                Ok(ColorSpace::Indexed(Box::new(base), lookup))
+            }
+            _ => ColorSpace::from_name(family),
+        }
+    }
+
+    fn from_name(name: &[u8]) -> Result<ColorSpace, ScanError> {
+        match name {
+            b"DeviceGray" | b"G" | b"CalGray" => Ok(ColorSpace::Gray),
+            b"DeviceRGB" | b"RGB" | b"CalRGB" => Ok(ColorSpace::Rgb),
+            b"DeviceCMYK" | b"CMYK" => Ok(ColorSpace::Cmyk),
+            name => Err(unsupported(&format!(
+                "{} color spaces",
+                String::from_utf8_lossy(name)
+            ))),
+        }
+    }
+
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed(..) => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+
+    /// Converts one pixel's components, scaled to 0..=255 (or, for indexed
+    /// colors, the raw index), to RGB.
+    fn to_rgb(&self, pixel: &[u8]) -> [u8; 3] {
+        match self {
+            ColorSpace::Gray => [pixel[0]; 3],
+            ColorSpace::Rgb => [pixel[0], pixel[1], pixel[2]],
+            ColorSpace::Cmyk => {
+                let k = 255 - pixel[3] as u16;
+                [0, 1, 2].map(|i| ((255 - pixel[i] as u16) * k / 255) as u8)
+            }
+            ColorSpace::Indexed(base, palette) => {
+                let n = base.components();
+                let start = pixel[0] as usize * n;
+                match palette.get(start..start + n) {
+                    Some(color) => base.to_rgb(color),
+                    None => [0; 3],
+                }
+            }
+        }
+    }
+}
+
+/// Builds a binary PPM from raw samples with `bits` per component; rows are
+/// padded to whole bytes.
+fn to_ppm(
+    width: u32,
+    height: u32,
+    bits: i64,
+    color_space: &ColorSpace,
+    samples: &[u8],
+) -> Result<Vec<u8>, ScanError> {
+    if ![1, 2, 4, 8, 16].contains(&bits) {
+        return Err(decode_error(&format!("{} bits per component", bits)));
+    }
+    if width == 0 || height == 0 {
+        return Err(decode_error("image without pixels"));
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(decode_error(&format!(
+            "{}x{} image is larger than {} megapixels",
+            width,
+            height,
+            MAX_PIXELS >> 20
+        )));
+    }
+    let bits = bits as usize;
+    let components = color_space.components();
+    let row_len = (width as usize * components * bits).div_ceil(8);
+    if samples.len() < row_len * height as usize {
+        return Err(decode_error("image data is shorter than its size"));
+    }
+    let indexed = matches!(color_space, ColorSpace::Indexed(..));
+    let max = (1u32 << bits.min(8)) - 1;
+
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    ppm.reserve(width as usize * height as usize * 3);
+    let mut pixel = vec![0u8; components];
+    for row in samples.chunks(row_len).take(height as usize) {
+        for x in 0..width as usize {
+            for (c, value) in pixel.iter_mut().enumerate() {
+                let index = x * components + c;
+                let sample = match bits {
+                    16 => row[index * 2] as u32,
+                    8 => row[index] as u32,
+                    _ => {
+                        let bit = index * bits;
+                        (row[bit / 8] as u32 >> (8 - bits - bit % 8)) & max
+                    }
+                };
+                *value = if indexed || bits >= 8 {
+                    sample as u8
+                } else {
+                    (sample * 255 / max) as u8
+                };
+            }
+            ppm.extend_from_slice(&color_space.to_rgb(&pixel));
+        }
+    }
+    Ok(ppm)
+}
+
+fn charge(budget: &mut Budget, len: usize) -> Result<(), ScanError> {
+    budget.charge(len).map_err(|err| ScanError {
+        kind: ErrorKind::Limit,
+        message: err.to_string(),
+    })
+}
+
+fn decode_error(message: &str) -> ScanError {
+    ScanError {
+        kind: ErrorKind::Decode,
+        message: message.to_string(),
+    }
+}
+
+fn unsupported(what: &str) -> ScanError {
+    ScanError {
+        kind: ErrorKind::UnsupportedFormat,
+        message: format!("{} are not supported", what),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(ppm: &[u8], width: u32, height: u32) -> &[u8] {
+        let header = format!("P6\n{} {}\n255\n", width, height);
+        assert!(ppm.starts_with(header.as_bytes()));
+        &ppm[header.len()..]
+    }
+
+    #[test]
+    fn zero_dimensions() {
+        assert!(to_ppm(0, 10, 8, &ColorSpace::Gray, &[]).is_err());
+        assert!(to_ppm(10, 0, 8, &ColorSpace::Gray, &[0; 10]).is_err());
+    }
+
+    #[test]
+    fn too_many_pixels() {
+        assert!(to_ppm(u32::MAX, u32::MAX, 8, &ColorSpace::Gray, &[]).is_err());
+    }
+
+    #[test]
+    fn unsupported_depth() {
+        assert!(to_ppm(1, 1, 3, &ColorSpace::Gray, &[0]).is_err());
+    }
+
+    #[test]
+    fn short_data() {
+        assert!(to_ppm(3, 2, 8, &ColorSpace::Rgb, &[0; 17]).is_err());
+    }
+
+    #[test]
+    fn odd_width_one_bit_rows_are_padded() {
+        // The padding bits are set, and must not leak into the next row.
+        let ppm = to_ppm(3, 2, 1, &ColorSpace::Gray, &[0b1010_0000, 0b0101_1111]).unwrap();
+        assert_eq!(
+            pixels(&ppm, 3, 2),
+            [[255; 3], [0; 3], [255; 3], [0; 3], [255; 3], [0; 3]].concat()
+        );
+    }
+
+    #[test]
+    fn odd_width_four_bit_rows_are_padded() {
+        let ppm = to_ppm(3, 2, 4, &ColorSpace::Gray, &[0xf0, 0x0f, 0x05, 0xaf]).unwrap();
+        assert_eq!(
+            pixels(&ppm, 3, 2),
+            [[255; 3], [0; 3], [0; 3], [0; 3], [85; 3], [170; 3]].concat()
+        );
+    }
+
+    #[test]
+    fn odd_width_rgb() {
+        let samples = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let ppm = to_ppm(3, 1, 8, &ColorSpace::Rgb, &samples).unwrap();
+        assert_eq!(pixels(&ppm, 3, 1), samples);
+    }
+
+    #[test]
+    fn sixteen_bits_keep_the_high_byte() {
+        let ppm = to_ppm(1, 1, 16, &ColorSpace::Gray, &[0xab, 0xcd]).unwrap();
+        assert_eq!(pixels(&ppm, 1, 1), [0xab; 3]);
+    }
+
+    #[test]
+    fn indexed() {
+        let palette = ColorSpace::Indexed(Box::new(ColorSpace::Rgb), vec![0, 0, 0, 10, 20, 30]);
+        // The third pixel is outside the palette.
+        let ppm = to_ppm(3, 1, 2, &palette, &[0b0100_1000]).unwrap();
+        assert_eq!(pixels(&ppm, 3, 1), [10, 20, 30, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,225 @@
+//! The scan pipeline: the directory walk, decoding and OCR all run at the same
+//! time, connected through bounded channels, and every image is handed to the
+//! reporters as soon as it is done. Decoding and OCR use fixed-size worker
+//! pools. When OCR falls behind, the decoders (and in turn the walk) block on
+//! a full queue, so only a few decoded images per job are held in memory at
+//! any time.
+//!
+//! PDFs, Office documents and archives are opened by the decoders rather than
+//! the walk, so that a parser bug triggered by one malformed file costs only
+//! that file.
+
+use anyhow::Result;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{self, Arc};
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::discovery::{self, Discovery, Found, Input};
+use crate::{load_image, process_image_with_ocr, ErrorKind, Img, Pixels, Scanner, Skipped};
+
+pub struct Pipeline {
+    /// Scanned images, in the order they finish.
+    pub results: mpsc::Receiver<Img>,
+    walk: JoinHandle<Vec<Skipped>>,
+    /// What the decoders could not look at inside documents.
+    skipped: Arc<sync::Mutex<Vec<Skipped>>>,
+    workers: JoinSet<Result<()>>,
+    cancel: watch::Sender<bool>,
+}
+
+impl Pipeline {
+    /// Starts the walk, with `jobs` decode and `jobs` OCR workers. `stdin`
+    /// is scanned first if given.
+    pub fn start(
+        discovery: Discovery,
+        stdin: Option<Input>,
+        scanner: Arc<Scanner>,
+        jobs: usize,
+    ) -> Pipeline {
+        let mut workers = JoinSet::new();
+        let (input_tx, input_rx) = mpsc::channel(jobs * 4);
+        let (decoded_tx, decoded_rx) = mpsc::channel::<(Img, Option<Pixels>)>(jobs);
+        let (result_tx, results) = mpsc::channel(jobs);
+        let (cancel, cancelled) = watch::channel(false);
+        let discovery = Arc::new(discovery);
+        let skipped = Arc::new(sync::Mutex::new(Vec::new()));
+
+        let walk_discovery = Arc::clone(&discovery);
+        let walk_cancelled = cancelled.clone();
+        let walk = tokio::task::spawn_blocking(move || {
+            if let Some(input) = stdin {
+                if input_tx.blocking_send(Found::Input(input)).is_err() {
+                    return Vec::new();
+                }
+            }
+            walk_discovery.walk(&input_tx, &walk_cancelled)
+        });
+        let decode_scanner = Arc::clone(&scanner);
+        let decode_cancelled = cancelled.clone();
+        let decode_skipped = Arc::clone(&skipped);
+        stage(
+            &mut workers,
+            jobs,
+            &cancelled,
+            input_rx,
+            decoded_tx,
+            move |found: Found, emit: &mut dyn FnMut((Img, Option<Pixels>)) -> bool| {
+                let mut decode = |input: Input| {
+                    let mut img = Img::new(input.path());
+                    let pixels = isolate(&mut img, ErrorKind::Decode, |img| {
+                        load_image(img, input, &decode_scanner)
+                    });
+                    emit((img, pixels.flatten()))
+                };
+                match found {
+                    Found::Input(input) => {
+                        decode(input);
+                    }
+                    Found::Document(path, container) => {
+                        let mut skipped = Vec::new();
+                        let expanded = catch_panic(|| {
+                            discovery.expand(
+                                &path,
+                                container,
+                                &decode_cancelled,
+                                &mut decode,
+                                &mut skipped,
+                            )
+                        });
+                        if let Err(message) = expanded {
+                            let message = format!("panicked: {}", message);
+                            discovery::skip(&mut skipped, &path, ErrorKind::Decode, message);
+                        }
+                        decode_skipped.lock().unwrap().extend(skipped);
+                    }
+                }
+            },
+        );
+        stage(
+            &mut workers,
+            jobs,
+            &cancelled,
+            decoded_rx,
+            result_tx,
+            move |(mut img, pixels): (Img, Option<Pixels>), emit: &mut dyn FnMut(Img) -> bool| {
+                isolate(&mut img, ErrorKind::Ocr, |img| {
+                    process_image_with_ocr(img, pixels.as_ref(), &scanner)
+                });
+                emit(img);
+            },
+        );
+
+        Pipeline {
+            results,
+            walk,
+            skipped,
+            workers,
+            cancel,
+        }
+    }
+
+    /// Stops the walk and lets every worker exit after the image it is
+    /// working on. Those images are not reported.
+    pub fn cancel(&self) {
+        let _ = self.cancel.send(true);
+    }
+
+    /// Waits for every worker to exit and returns what the walk and the
+    /// decoders had to skip. Call once `results` is drained, or after
+    /// `cancel`.
+    pub async fn finish(mut self) -> Result<Vec<Skipped>> {
+        self.results.close();
+        while let Some(worker) = self.workers.join_next().await {
+            worker??;
+        }
+        let mut skipped = self.walk.await?;
+        skipped.append(&mut self.skipped.lock().unwrap());
+        Ok(skipped)
+    }
+}
+
+/// Spawns `count` workers that take items from `input` and run `work` on each
+/// one on the blocking thread pool. `work` passes its results on to `output`
+/// through the callback it is given, which returns false once the scan is
+/// cancelled or nobody is listening anymore.
+fn stage<T, U, F>(
+    workers: &mut JoinSet<Result<()>>,
+    count: usize,
+    cancelled: &watch::Receiver<bool>,
+    input: mpsc::Receiver<T>,
+    output: mpsc::Sender<U>,
+    work: F,
+) where
+    T: Send + 'static,
+    U: Send + 'static,
+    F: Fn(T, &mut dyn FnMut(U) -> bool) + Send + Sync + 'static,
+{
+    let input = Arc::new(Mutex::new(input));
+    let work = Arc::new(work);
+    for _ in 0..count {
+        let input = Arc::clone(&input);
+        let output = output.clone();
+        let work = Arc::clone(&work);
+        let mut cancelled = cancelled.clone();
+        workers.spawn(async move {
+            loop {
+                let item = tokio::select! {
+                    item = async { input.lock().await.recv().await } => item,
+                    _ = cancelled.wait_for(|&cancelled| cancelled) => None,
+                };
+                let Some(item) = item else {
+                    break;
+                };
+                let work = Arc::clone(&work);
+                let output = output.clone();
+                let cancelled = cancelled.clone();
+                let listening = tokio::task::spawn_blocking(move || {
+                    let mut listening = true;
+                    work(item, &mut |result| {
+                        listening = listening
+                            && !*cancelled.borrow()
+                            && output.blocking_send(result).is_ok();
+                        listening
+                    });
+                    listening
+                })
+                .await?;
+                if !listening {
+                    break;
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Runs `work` on `img`, recording a panic as an error of `kind` so that one
+/// bad image (say, a decoder bug triggered by a malformed file) does not take
+/// down the whole scan.
+fn isolate<T>(img: &mut Img, kind: ErrorKind, work: impl FnOnce(&mut Img) -> T) -> Option<T> {
+    match catch_panic(|| work(img)) {
+        Ok(value) => Some(value),
+        Err(message) => {
+            img.error(kind, format!("panicked: {}", message));
+            None
+        }
+    }
+}
+
+/// Runs `work`, turning a panic into its message.
+fn catch_panic<T>(work: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(work))
+        .map_err(|payload| panic_message(&*payload).to_string())
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
@@ -0,0 +1,307 @@
+//! Self-contained HTML report: a thumbnail of every flagged image with the
+//! matching OCR lines outlined and the secrets blurred, plus a sortable
+//! table of all findings. Everything, including images, is inlined.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use image::{imageops, ImageFormat, Rgb, RgbImage};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+use super::{describe_location, Reporter, Summary, TOOL};
+use crate::{BoundingBox, Finding, Img, OcrLine};
+
+/// Longest side of the embedded thumbnails, in pixels.
+const THUMBNAIL_SIZE: u32 = 640;
+const OUTLINE: Rgb<u8> = Rgb([230, 30, 30]);
+
+pub struct HtmlReporter {
+    out: BufWriter<File>,
+    cards: String,
+    card_count: usize,
+    rows: String,
+    errors: String,
+}
+
+impl HtmlReporter {
+    pub fn create(path: &Path) -> Result<HtmlReporter> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create HTML report {}", path.display()))?;
+        Ok(HtmlReporter {
+            out: BufWriter::new(file),
+            cards: String::new(),
+            card_count: 0,
+            rows: String::new(),
+            errors: String::new(),
+        })
+    }
+}
+
+impl Reporter for HtmlReporter {
+    fn image(&mut self, img: &Img) -> Result<()> {
+        for error in &img.errors {
+            writeln!(
+                self.errors,
+                "<li><code>{}</code>: {}</li>",
+                escape(&img.path),
+                escape(&error.to_string())
+            )?;
+        }
+        if img.findings.is_empty() {
+            return Ok(());
+        }
+
+        let anchor = format!("img-{}", self.card_count);
+        self.card_count += 1;
+        writeln!(
+            self.cards,
+            "<section class=\"card\" id=\"{}\"><h2><code>{}</code>{}</h2>",
+            anchor,
+            escape(&img.path),
+            img.placement
+                .map(|placement| format!(" ({})", placement))
+                .unwrap_or_default()
+        )?;
+        if let Some(png) = &img.thumbnail {
+            writeln!(
+                self.cards,
+                "<img alt=\"\" src=\"data:image/png;base64,{}\">",
+                base64::engine::general_purpose::STANDARD.encode(png)
+            )?;
+        }
+        writeln!(self.cards, "<ul>")?;
+        for finding in &img.findings {
+            writeln!(
+                self.cards,
+                "<li><b>{}</b> ({}) on {}: <code>{}</code></li>",
+                escape(&finding.rule_id),
+                finding.severity,
+                describe_location(finding),
+                escape(&finding.preview)
+            )?;
+            writeln!(
+                self.rows,
+                "<tr><td><a href=\"#{}\"><code>{}</code></a></td><td>{}</td>\
+                 <td data-sort=\"{}\">{}</td><td>{}</td><td><code>{}</code></td><td>{:.2}</td></tr>",
+                anchor,
+                escape(&img.path),
+                escape(&finding.rule_id),
+                finding.severity as u8,
+                finding.severity,
+                describe_location(finding),
+                escape(&finding.preview),
+                finding.confidence
+            )?;
+        }
+        writeln!(self.cards, "</ul></section>")?;
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<()> {
+        let out = &mut self.out;
+        write!(out, "{}", HEAD)?;
+        writeln!(
+            out,
+            "<h1>{} {} report</h1><p>Scanned {} images: {} findings in {} images, {}.</p>",
+            TOOL.name,
+            TOOL.version,
+            summary.images,
+            summary.findings,
+            summary.images_with_findings,
+            escape(&summary.describe_errors())
+        )?;
+        if summary.interrupted {
+            writeln!(
+                out,
+                "<p><strong>Scan interrupted; this report is incomplete.</strong></p>"
+            )?;
+        }
+        writeln!(
+            out,
+            "<table id=\"findings\"><thead><tr><th>Image</th><th>Rule</th><th>Severity</th>\
+             <th>Line</th><th>Preview</th><th>Confidence</th></tr></thead><tbody>"
+        )?;
+        write!(out, "{}", self.rows)?;
+        writeln!(out, "</tbody></table>")?;
+        write!(out, "{}", self.cards)?;
+        if !self.errors.is_empty() {
+            writeln!(out, "<h2>Errors</h2><ul>{}</ul>", self.errors)?;
+        }
+        if !summary.coverage.complete {
+            writeln!(
+                out,
+                "<h2>Skipped during discovery</h2><p>Coverage incomplete: {}.</p><ul>",
+                escape(&summary.coverage.describe())
+            )?;
+            for skip in &summary.coverage.skipped {
+                writeln!(
+                    out,
+                    "<li><code>{}</code>: {}</li>",
+                    escape(&skip.path),
+                    escape(&skip.error.to_string())
+                )?;
+            }
+            writeln!(out, "</ul>")?;
+        }
+        write!(out, "{}", TAIL)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Downscales `img`, blurs every secret and outlines the OCR lines they were
+/// found on. Returns PNG bytes.
+pub fn render_thumbnail(
+    img: &RgbImage,
+    lines: &[OcrLine],
+    findings: &[&Finding],
+) -> Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let scale = (THUMBNAIL_SIZE as f32 / width.max(height) as f32).min(1.0);
+    let mut thumb = imageops::resize(
+        img,
+        ((width as f32 * scale) as u32).max(1),
+        ((height as f32 * scale) as u32).max(1),
+        imageops::FilterType::Triangle,
+    );
+    let scaled = |b: BoundingBox| BoundingBox {
+        x: (b.x as f32 * scale) as i32,
+        y: (b.y as f32 * scale) as i32,
+        width: ((b.width as f32 * scale).ceil() as i32).max(1),
+        height: ((b.height as f32 * scale).ceil() as i32).max(1),
+    };
+
+    for finding in findings {
+        let line = &lines[finding.line];
+        blur(&mut thumb, scaled(secret_rect(line, finding)));
+    }
+    for finding in findings {
+        outline(&mut thumb, scaled(lines[finding.line].bbox));
+    }
+
+    let mut png = Vec::new();
+    thumb.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Estimates where the secret sits within its line, assuming characters of
+/// roughly equal width.
+fn secret_rect(line: &OcrLine, finding: &Finding) -> BoundingBox {
+    let total = line.text.chars().count().max(1) as f32;
+    let before = line.text[..finding.start].chars().count() as f32;
+    let len = line.text[finding.start..finding.end].chars().count() as f32;
+    let char_width = line.bbox.width as f32 / total;
+    BoundingBox {
+        x: line.bbox.x + (before * char_width) as i32,
+        y: line.bbox.y,
+        width: (len * char_width).ceil() as i32,
+        height: line.bbox.height,
+    }
+}
+
+/// Clamps `rect` to the image, returning `(x, y, width, height)`.
+fn clamp(img: &RgbImage, rect: BoundingBox) -> Option<(u32, u32, u32, u32)> {
+    let (width, height) = img.dimensions();
+    let x0 = rect.x.clamp(0, width as i32) as u32;
+    let y0 = rect.y.clamp(0, height as i32) as u32;
+    let x1 = (rect.x + rect.width).clamp(0, width as i32) as u32;
+    let y1 = (rect.y + rect.height).clamp(0, height as i32) as u32;
+    (x1 > x0 && y1 > y0).then(|| (x0, y0, x1 - x0, y1 - y0))
+}
+
+fn blur(img: &mut RgbImage, rect: BoundingBox) {
+    // Pad a little so OCR boxes that are slightly too tight still hide
+    // the edges of the secret.
+    let pad = rect.height / 4 + 1;
+    let padded = BoundingBox {
+        x: rect.x - pad,
+        y: rect.y - pad,
+        width: rect.width + 2 * pad,
+        height: rect.height + 2 * pad,
+    };
+    let Some((x, y, w, h)) = clamp(img, padded) else {
+        return;
+    };
+    let region = imageops::crop_imm(img, x, y, w, h).to_image();
+    let sigma = (h as f32 / 2.0).max(3.0);
+    let blurred = imageops::blur(&region, sigma);
+    imageops::replace(img, &blurred, x as i64, y as i64);
+}
+
+fn outline(img: &mut RgbImage, rect: BoundingBox) {
+    let Some((x, y, w, h)) = clamp(img, rect) else {
+        return;
+    };
+    for t in 0..2.min(w).min(h) {
+        for dx in 0..w {
+            img.put_pixel(x + dx, y + t, OUTLINE);
+            img.put_pixel(x + dx, y + h - 1 - t, OUTLINE);
+        }
+        for dy in 0..h {
+            img.put_pixel(x + t, y + dy, OUTLINE);
+            img.put_pixel(x + w - 1 - t, y + dy, OUTLINE);
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HEAD: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>EvilEye report</title>
+<style>
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f0f0f0; cursor: pointer; user-select: none; }
+th.asc::after { content: " \25B2"; }
+th.desc::after { content: " \25BC"; }
+.card { border: 1px solid #ddd; border-radius: 4px; padding: 1em; margin-bottom: 1.5em; }
+.card img { max-width: 100%; border: 1px solid #eee; }
+h2 { font-size: 1em; word-break: break-all; }
+</style>
+</head>
+<body>
+"#;
+
+const TAIL: &str = r##"<script>
+document.querySelectorAll("#findings th").forEach((th, column) => {
+  th.addEventListener("click", () => {
+    const tbody = th.closest("table").tBodies[0];
+    const ascending = !th.classList.contains("asc");
+    th.parentNode.querySelectorAll("th").forEach(h => h.classList.remove("asc", "desc"));
+    th.classList.add(ascending ? "asc" : "desc");
+    const key = row => {
+      const cell = row.cells[column];
+      const value = cell.dataset.sort ?? cell.textContent;
+      const number = Number(value);
+      return Number.isNaN(number) ? value.toLowerCase() : number;
+    };
+    const rows = Array.from(tbody.rows).sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      return (x < y ? -1 : x > y ? 1 : 0) * (ascending ? 1 : -1);
+    });
+    rows.forEach(row => tbody.appendChild(row));
+  });
+});
+</script>
+</body>
+</html>
+"##;
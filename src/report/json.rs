@@ -0,0 +1,86 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+use super::{Reporter, Summary, Tool, SCHEMA_VERSION, TOOL};
+use crate::Img;
+
+/// Writes a single JSON document. Images are streamed into the `images`
+/// array so the whole report never has to be held in memory.
+pub struct JsonReporter {
+    out: Box<dyn Write + Send>,
+    first: bool,
+}
+
+impl JsonReporter {
+    pub fn new(mut out: Box<dyn Write + Send>) -> Result<JsonReporter> {
+        write!(out, "{{\"schema_version\":{},\"tool\":", SCHEMA_VERSION)?;
+        serde_json::to_writer(&mut out, &TOOL)?;
+        write!(out, ",\"images\":[")?;
+        Ok(JsonReporter { out, first: true })
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn image(&mut self, img: &Img) -> Result<()> {
+        if !self.first {
+            write!(self.out, ",")?;
+        }
+        self.first = false;
+        serde_json::to_writer(&mut self.out, img)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<()> {
+        write!(self.out, "],\"summary\":")?;
+        serde_json::to_writer(&mut self.out, summary)?;
+        writeln!(self.out, "}}")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes one self-describing JSON record per line, flushed as soon as each
+/// image is done, followed by a final `summary` record.
+pub struct NdjsonReporter {
+    out: Box<dyn Write + Send>,
+}
+
+#[derive(Serialize)]
+struct Record<'a, T: Serialize> {
+    schema_version: u32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    tool: &'a Tool,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+impl NdjsonReporter {
+    pub fn new(out: Box<dyn Write + Send>) -> NdjsonReporter {
+        NdjsonReporter { out }
+    }
+
+    fn write<T: Serialize>(&mut self, kind: &'static str, data: &T) -> Result<()> {
+        let record = Record {
+            schema_version: SCHEMA_VERSION,
+            kind,
+            tool: &TOOL,
+            data,
+        };
+        serde_json::to_writer(&mut self.out, &record)?;
+        writeln!(self.out)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl Reporter for NdjsonReporter {
+    fn image(&mut self, img: &Img) -> Result<()> {
+        self.write("image", img)
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<()> {
+        self.write("summary", summary)
+    }
+}
@@ -0,0 +1,172 @@
+pub mod html;
+mod json;
+mod sarif;
+mod text;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::rules::RuleSet;
+use crate::{ErrorKind, Finding, Img, Skipped};
+
+/// Bumped whenever a field is removed or changes meaning in the JSON output.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+    Sarif,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 4] = ["text", "json", "ndjson", "sarif"];
+
+    pub fn from_name(name: &str) -> Result<Format> {
+        Ok(match name {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            "ndjson" => Format::Ndjson,
+            "sarif" => Format::Sarif,
+            _ => bail!("Unknown output format {:?}", name),
+        })
+    }
+}
+
+/// Receives each image as soon as it has been scanned, then the totals once
+/// the scan is over.
+pub trait Reporter {
+    fn image(&mut self, img: &Img) -> Result<()>;
+    fn finish(&mut self, summary: &Summary) -> Result<()>;
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    /// The scan was stopped early (Ctrl-C), so this report is partial.
+    pub interrupted: bool,
+    pub images: usize,
+    /// Images whose OCR results came from the cache.
+    pub cached: usize,
+    pub images_with_findings: usize,
+    pub findings: usize,
+    pub errors: usize,
+    pub errors_by_kind: BTreeMap<ErrorKind, usize>,
+    pub coverage: Coverage,
+}
+
+/// Whether the walk looked at everything under the scanned root.
+#[derive(Debug, Serialize)]
+pub struct Coverage {
+    pub complete: bool,
+    pub skipped_by_kind: BTreeMap<ErrorKind, usize>,
+    pub skipped: Vec<Skipped>,
+}
+
+impl Coverage {
+    pub fn new(skipped: Vec<Skipped>) -> Coverage {
+        let mut skipped_by_kind = BTreeMap::new();
+        for skip in &skipped {
+            *skipped_by_kind.entry(skip.error.kind).or_default() += 1;
+        }
+        Coverage {
+            complete: skipped.is_empty(),
+            skipped_by_kind,
+            skipped,
+        }
+    }
+
+    /// `"2 paths skipped (permission: 2)"`, for human readable reports.
+    pub fn describe(&self) -> String {
+        describe_counts(
+            &format!("{} paths skipped", self.skipped.len()),
+            &self.skipped_by_kind,
+        )
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new(Vec::new())
+    }
+}
+
+impl Summary {
+    pub fn record(&mut self, img: &Img) {
+        self.images += 1;
+        if img.cached {
+            self.cached += 1;
+        }
+        if !img.findings.is_empty() {
+            self.images_with_findings += 1;
+        }
+        self.findings += img.findings.len();
+        self.errors += img.errors.len();
+        for error in &img.errors {
+            *self.errors_by_kind.entry(error.kind).or_default() += 1;
+        }
+    }
+
+    /// `"3 errors (decode: 2, io: 1)"`, for human readable reports.
+    pub fn describe_errors(&self) -> String {
+        describe_counts(&format!("{} errors", self.errors), &self.errors_by_kind)
+    }
+}
+
+fn describe_counts(total: &str, by_kind: &BTreeMap<ErrorKind, usize>) -> String {
+    if by_kind.is_empty() {
+        return total.to_string();
+    }
+    let kinds = by_kind
+        .iter()
+        .map(|(kind, count)| format!("{}: {}", kind, count))
+        .collect::<Vec<_>>();
+    format!("{} ({})", total, kinds.join(", "))
+}
+
+/// `"line 3"`, `"frame 41 at 3.20s, line 3"` in animations or `"page 2,
+/// line 3"` in multi-page TIFFs and PDFs, counting from 1.
+fn describe_location(finding: &Finding) -> String {
+    let line = line_number(finding);
+    match (finding.frame, finding.timestamp_ms, finding.page) {
+        (Some(frame), Some(ms), _) => format!(
+            "frame {} at {:.2}s, line {}",
+            frame + 1,
+            ms as f64 / 1000.0,
+            line
+        ),
+        (_, _, Some(page)) => format!("page {}, line {}", page + 1, line),
+        _ => format!("line {}", line),
+    }
+}
+
+/// The line of a finding within its frame or page, counting from 1.
+fn line_number(finding: &Finding) -> usize {
+    finding.local_line.unwrap_or(finding.line) + 1
+}
+
+#[derive(Serialize)]
+struct Tool {
+    name: &'static str,
+    version: &'static str,
+}
+
+const TOOL: Tool = Tool {
+    name: env!("CARGO_PKG_NAME"),
+    version: env!("CARGO_PKG_VERSION"),
+};
+
+pub fn create(
+    format: Format,
+    out: Box<dyn Write + Send>,
+    rules: &RuleSet,
+) -> Result<Box<dyn Reporter + Send>> {
+    Ok(match format {
+        Format::Text => Box::new(text::TextReporter::new(out)),
+        Format::Json => Box::new(json::JsonReporter::new(out)?),
+        Format::Ndjson => Box::new(json::NdjsonReporter::new(out)),
+        Format::Sarif => Box::new(sarif::SarifReporter::new(out, rules)?),
+    })
+}
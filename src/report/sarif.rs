@@ -0,0 +1,251 @@
+//! SARIF 2.1.0 output. Each finding becomes a result located in the image
+//! file; the pixel rectangle of the OCR line it was read from is attached as
+//! `boundingBox` in the region's property bag, since SARIF regions have no
+//! notion of image coordinates. Images inside archives and documents are
+//! located in the file on disk, with the path inside it in the artifact
+//! location's property bag.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+use super::{describe_location, line_number, Reporter, Summary, TOOL};
+use crate::rules::{RuleSet, Severity};
+use crate::{Finding, Img};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+pub struct SarifReporter {
+    out: Box<dyn Write + Send>,
+    rule_ids: Vec<String>,
+    first: bool,
+    notifications: Vec<Value>,
+}
+
+impl SarifReporter {
+    pub fn new(mut out: Box<dyn Write + Send>, rules: &RuleSet) -> Result<SarifReporter> {
+        let descriptors = rules
+            .rules
+            .iter()
+            .map(|rule| {
+                let description = if rule.description.is_empty() {
+                    rule.id.clone()
+                } else {
+                    rule.description.clone()
+                };
+                let mut tags = vec!["security".to_string()];
+                tags.extend(rule.tags.iter().cloned());
+                json!({
+                    "id": rule.id,
+                    "name": rule.id,
+                    "shortDescription": { "text": description },
+                    "fullDescription": { "text": description },
+                    "defaultConfiguration": { "level": level(rule.severity) },
+                    "properties": {
+                        "tags": tags,
+                        "severity": rule.severity,
+                        "security-severity": security_severity(rule.severity),
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        let driver = json!({
+            "name": TOOL.name,
+            "version": TOOL.version,
+            "informationUri": "https://github.com/startupsecurity/evileye",
+            "rules": descriptors,
+        });
+
+        // The results array is streamed; everything after it is written by
+        // `finish`.
+        write!(
+            out,
+            "{{\"$schema\":\"{}\",\"version\":\"2.1.0\",\"runs\":[{{\"tool\":{{\"driver\":",
+            SARIF_SCHEMA
+        )?;
+        serde_json::to_writer(&mut out, &driver)?;
+        write!(out, "}},\"results\":[")?;
+
+        Ok(SarifReporter {
+            out,
+            rule_ids: rules.rules.iter().map(|r| r.id.clone()).collect(),
+            first: true,
+            notifications: Vec::new(),
+        })
+    }
+}
+
+impl Reporter for SarifReporter {
+    fn image(&mut self, img: &Img) -> Result<()> {
+        let artifact = artifact_location(&img.path);
+        for finding in &img.findings {
+            let line = &img.lines[finding.line];
+            let start_column = line.text[..finding.start].chars().count() + 1;
+            let end_column = start_column + line.text[finding.start..finding.end].chars().count();
+            let mut result = json!({
+                "ruleId": finding.rule_id,
+                "level": level(finding.severity),
+                "message": {
+                    "text": format!(
+                        "Possible secret ({}) on OCR {}: {}",
+                        finding.rule_id,
+                        describe_location(finding),
+                        finding.preview
+                    ),
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": artifact,
+                        "region": {
+                            "startLine": line_number(finding),
+                            "startColumn": start_column,
+                            "endColumn": end_column,
+                            "properties": { "boundingBox": line.bbox },
+                        },
+                    },
+                }],
+                "partialFingerprints": {
+                    "evileyeFinding/v1": fingerprint(img, finding),
+                },
+                "properties": {
+                    "confidence": finding.confidence,
+                    "imageSha256": img.hashes.sha256,
+                    "dimensions": img.dimensions,
+                },
+            });
+            if let (Some(frame), Some(ms)) = (finding.frame, finding.timestamp_ms) {
+                result["properties"]["frame"] = json!(frame);
+                result["properties"]["timestampMs"] = json!(ms);
+            }
+            if let Some(page) = finding.page {
+                result["properties"]["page"] = json!(page);
+            }
+            if let Some(placement) = img.placement {
+                result["properties"]["placement"] = json!(placement);
+            }
+            if let Some(index) = self.rule_ids.iter().position(|id| *id == finding.rule_id) {
+                result["ruleIndex"] = json!(index);
+            }
+
+            if !self.first {
+                write!(self.out, ",")?;
+            }
+            self.first = false;
+            serde_json::to_writer(&mut self.out, &result)?;
+        }
+
+        for error in &img.errors {
+            self.notifications.push(json!({
+                "level": "error",
+                "descriptor": { "id": error.kind },
+                "message": { "text": error.to_string() },
+                "locations": [{
+                    "physicalLocation": { "artifactLocation": artifact },
+                }],
+            }));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<()> {
+        for skip in &summary.coverage.skipped {
+            self.notifications.push(json!({
+                "level": "warning",
+                "descriptor": { "id": skip.error.kind },
+                "message": { "text": format!("Skipped during discovery: {}", skip.error) },
+                "locations": [{
+                    "physicalLocation": { "artifactLocation": artifact_location(&skip.path) },
+                }],
+            }));
+        }
+        let invocation = json!({
+            "executionSuccessful": !summary.interrupted,
+            "toolExecutionNotifications": self.notifications,
+            "properties": { "summary": summary },
+        });
+        write!(self.out, "],\"invocations\":[")?;
+        serde_json::to_writer(&mut self.out, &invocation)?;
+        writeln!(self.out, "]}}]}}")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low => "note",
+    }
+}
+
+/// Score used by GitHub code scanning to bucket security alerts.
+fn security_severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "9.5",
+        Severity::High => "8.0",
+        Severity::Medium => "5.5",
+        Severity::Low => "3.0",
+    }
+}
+
+/// Tells apart every finding in an image: two secrets on the same line
+/// differ in where they start.
+fn fingerprint(img: &Img, finding: &Finding) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(img.hashes.sha256.as_deref().unwrap_or(&img.path));
+    hasher.update([0]);
+    hasher.update(&finding.rule_id);
+    hasher.update([0]);
+    for position in [
+        finding.frame,
+        finding.page,
+        Some(finding.line),
+        Some(finding.start),
+    ] {
+        hasher.update(position.map_or(0, |n| n as u64 + 1).to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Locates `path` in the outermost file on disk; the rest of an
+/// `outer.zip!/inner.tar!/shot.png` path goes in `properties.embeddedPath`.
+fn artifact_location(path: &str) -> Value {
+    match path.split_once("!/") {
+        Some((file, inner)) => json!({
+            "uri": path_to_uri(file),
+            "properties": { "embeddedPath": inner },
+        }),
+        None => json!({ "uri": path_to_uri(path) }),
+    }
+}
+
+/// Relative paths stay relative (resolved against the SARIF file's location by
+/// consumers); absolute paths become `file://` URIs.
+fn path_to_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut uri = String::new();
+    if path.starts_with('/') {
+        uri.push_str("file://");
+    } else if path.chars().nth(1) == Some(':') {
+        uri.push_str("file:///");
+    }
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/'
+            | b':'
+            | b'!' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
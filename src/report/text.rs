@@ -0,0 +1,111 @@
+use anyhow::Result;
+use std::io::Write;
+
+use super::{describe_location, Reporter, Summary};
+use crate::Img;
+
+/// Skipped paths beyond this many are only counted; the JSON formats list
+/// them all.
+const MAX_SKIPPED_LISTED: usize = 20;
+
+pub struct TextReporter {
+    out: Box<dyn Write + Send>,
+}
+
+impl TextReporter {
+    pub fn new(out: Box<dyn Write + Send>) -> TextReporter {
+        TextReporter { out }
+    }
+}
+
+impl Reporter for TextReporter {
+    fn image(&mut self, img: &Img) -> Result<()> {
+        if img.text.is_none() && img.findings.is_empty() && img.errors.is_empty() {
+            return Ok(());
+        }
+        let out = &mut self.out;
+        writeln!(out, "-----------------------------------")?;
+        writeln!(out, "Image Path: {}", img.path)?;
+        if let Some(placement) = img.placement {
+            writeln!(out, "Shown on: {}", placement)?;
+        }
+        if let Some(frames) = &img.frames {
+            writeln!(
+                out,
+                "Frames: {} of {} OCR'd{}",
+                frames.scanned,
+                frames.total,
+                if frames.truncated {
+                    " (stopped at --max-frames)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        if let Some(pages) = img.pages {
+            writeln!(out, "Pages: {}", pages)?;
+        }
+        for error in &img.errors {
+            writeln!(out, "Error processing image: {}", error)?;
+        }
+        if let Some(text) = &img.text {
+            writeln!(out, "Extracted Text:")?;
+            for line in text {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        if img.errors.is_empty() {
+            writeln!(out, "Contains Secrets: {}", !img.findings.is_empty())?;
+        }
+        for finding in &img.findings {
+            writeln!(
+                out,
+                "  [{}] {} {} ({}..{}): {} (confidence {:.2})",
+                finding.rule_id,
+                finding.severity,
+                describe_location(finding),
+                finding.start,
+                finding.end,
+                finding.preview,
+                finding.confidence
+            )?;
+        }
+        writeln!(out, "-----------------------------------")?;
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<()> {
+        writeln!(
+            self.out,
+            "Scanned {} images{}: {} findings in {} images, {}",
+            summary.images,
+            if summary.cached > 0 {
+                format!(" ({} from cache)", summary.cached)
+            } else {
+                String::new()
+            },
+            summary.findings,
+            summary.images_with_findings,
+            summary.describe_errors()
+        )?;
+        if summary.interrupted {
+            writeln!(self.out, "Scan interrupted; this report is incomplete")?;
+        }
+        let coverage = &summary.coverage;
+        if !coverage.complete {
+            writeln!(self.out, "Coverage incomplete: {}", coverage.describe())?;
+            for skip in coverage.skipped.iter().take(MAX_SKIPPED_LISTED) {
+                writeln!(self.out, "  {}: {}", skip.path, skip.error)?;
+            }
+            if coverage.skipped.len() > MAX_SKIPPED_LISTED {
+                writeln!(
+                    self.out,
+                    "  ... and {} more",
+                    coverage.skipped.len() - MAX_SKIPPED_LISTED
+                )?;
+            }
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use crate::gitleaks;
+
+const DEFAULT_RULES: &str = include_str!("../rules/default.toml");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub const NAMES: [&'static str; 4] = ["low", "medium", "high", "critical"];
+
+    pub fn from_name(name: &str) -> Result<Severity> {
+        Ok(match name {
+            "low" => Severity::Low,
+            "medium" => Severity::Medium,
+            "high" => Severity::High,
+            "critical" => Severity::Critical,
+            _ => bail!("Unknown severity {:?}", name),
+        })
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    pub id: String,
+    pub description: String,
+    pub regex: Regex,
+    /// Capture group holding the secret; the whole match when unset.
+    pub secret_group: Option<usize>,
+    /// Minimum Shannon entropy the secret must have to be reported.
+    pub entropy: Option<f64>,
+    /// Lowercased; a line must contain one of these for the regex to run.
+    pub keywords: Vec<String>,
+    pub severity: Severity,
+    pub tags: Vec<String>,
+    pub allowlists: Vec<Allowlist>,
+}
+
+impl Rule {
+    pub fn matches_keywords(&self, lowercased_line: &str) -> bool {
+        self.keywords.is_empty() || self.keywords.iter().any(|k| lowercased_line.contains(k))
+    }
+}
+
+/// Which text an allowlist's regexes are tested against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RegexTarget {
+    #[default]
+    Secret,
+    Match,
+    Line,
+}
+
+/// Conditions under which an otherwise matching secret is not reported.
+#[derive(Debug, Default)]
+pub struct Allowlist {
+    /// All configured checks must pass instead of any one of them.
+    pub require_all: bool,
+    pub regex_target: RegexTarget,
+    pub regexes: Vec<Regex>,
+    /// Matched against the path of the scanned image.
+    pub paths: Vec<Regex>,
+    /// Lowercased; the secret is allowed if it contains any of these.
+    pub stopwords: Vec<String>,
+    /// Rule ids this allowlist is limited to; empty means every rule.
+    pub target_rules: Vec<String>,
+}
+
+/// A single candidate secret, as seen by allowlists.
+pub struct Candidate<'a> {
+    pub path: &'a str,
+    pub line: &'a str,
+    pub matched: &'a str,
+    pub secret: &'a str,
+}
+
+impl Allowlist {
+    pub fn allows(&self, candidate: &Candidate) -> bool {
+        let target = match self.regex_target {
+            RegexTarget::Secret => candidate.secret,
+            RegexTarget::Match => candidate.matched,
+            RegexTarget::Line => candidate.line,
+        };
+        let mut checks = Vec::new();
+        if !self.regexes.is_empty() {
+            checks.push(self.regexes.iter().any(|r| r.is_match(target)));
+        }
+        if !self.paths.is_empty() {
+            checks.push(self.paths.iter().any(|r| r.is_match(candidate.path)));
+        }
+        if !self.stopwords.is_empty() {
+            let secret = candidate.secret.to_lowercase();
+            checks.push(self.stopwords.iter().any(|w| secret.contains(w)));
+        }
+        if self.require_all {
+            !checks.is_empty() && checks.iter().all(|&c| c)
+        } else {
+            checks.iter().any(|&c| c)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+    /// Allowlists that apply across rules.
+    pub allowlists: Vec<Allowlist>,
+}
+
+/// Rules are kept as `V`, the format's own values, so that errors in one
+/// can be reported with its position and id.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile<V> {
+    #[serde(default = "Vec::new")]
+    rules: Vec<V>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    id: String,
+    #[serde(default)]
+    description: String,
+    regex: String,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    severity: Severity,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl RuleSet {
+    /// Loads the bundled rules (unless `with_defaults` is false), each file in
+    /// `paths` and then each gitleaks config in `gitleaks_paths`. Later rules
+    /// replace earlier ones with the same id. Also returns warnings about
+    /// gitleaks settings that could not be carried over.
+    pub fn load(
+        paths: &[impl AsRef<Path>],
+        gitleaks_paths: &[impl AsRef<Path>],
+        with_defaults: bool,
+    ) -> Result<(RuleSet, Vec<String>)> {
+        let mut set = RuleSet::default();
+        let mut warnings = Vec::new();
+        if with_defaults {
+            set.extend(parse_toml("<bundled rules>", DEFAULT_RULES)?);
+        }
+        for path in paths {
+            let path = path.as_ref();
+            set.extend(load_file(path)?);
+        }
+        for path in gitleaks_paths {
+            let config = gitleaks::load(path.as_ref())?;
+            set.extend(config.rules);
+            set.allowlists.extend(config.allowlists);
+            warnings.extend(config.warnings);
+        }
+        if set.rules.is_empty() {
+            bail!("No secret detection rules loaded");
+        }
+        Ok((set, warnings))
+    }
+
+    /// Whether any of the rule's or the global allowlists suppress the
+    /// candidate.
+    pub fn is_allowed(&self, rule: &Rule, candidate: &Candidate) -> bool {
+        rule.allowlists.iter().any(|a| a.allows(candidate))
+            || self.allowlists.iter().any(|a| {
+                (a.target_rules.is_empty() || a.target_rules.contains(&rule.id))
+                    && a.allows(candidate)
+            })
+    }
+
+    fn extend(&mut self, rules: Vec<Rule>) {
+        for rule in rules {
+            match self.rules.iter_mut().find(|r| r.id == rule.id) {
+                Some(existing) => *existing = rule,
+                None => self.rules.push(rule),
+            }
+        }
+    }
+}
+
+fn load_file(path: &Path) -> Result<Vec<Rule>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read rule file {}", path.display()))?;
+    let source = path.display().to_string();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => parse_toml(&source, &contents),
+        Some("yaml" | "yml") => parse_yaml(&source, &contents),
+        _ => bail!(
+            "{}: rule files must have a .toml, .yaml or .yml extension",
+            source
+        ),
+    }
+}
+
+fn parse_toml(source: &str, contents: &str) -> Result<Vec<Rule>> {
+    let file: RuleFile<toml::Value> =
+        toml::from_str(contents).map_err(|e| anyhow!("{}: {}", source, e))?;
+    let specs = file.rules.into_iter().map(|value| {
+        let id = value
+            .get("id")
+            .and_then(toml::Value::as_str)
+            .map(str::to_string);
+        (id, RuleSpec::deserialize(value).map_err(|e| e.to_string()))
+    });
+    compile(source, specs)
+}
+
+fn parse_yaml(source: &str, contents: &str) -> Result<Vec<Rule>> {
+    let file: RuleFile<serde_yaml::Value> =
+        serde_yaml::from_str(contents).map_err(|e| anyhow!("{}: {}", source, e))?;
+    let specs = file.rules.into_iter().map(|value| {
+        let id = value
+            .get("id")
+            .and_then(serde_yaml::Value::as_str)
+            .map(str::to_string);
+        (id, RuleSpec::deserialize(value).map_err(|e| e.to_string()))
+    });
+    compile(source, specs)
+}
+
+/// Checks and compiles each rule, given with its id as far as it could be
+/// read.
+fn compile(
+    source: &str,
+    specs: impl Iterator<Item = (Option<String>, Result<RuleSpec, String>)>,
+) -> Result<Vec<Rule>> {
+    let mut seen = HashSet::new();
+    specs
+        .enumerate()
+        .map(|(index, (id, spec))| {
+            let at = format!(
+                "{}: rule #{} (id {:?})",
+                source,
+                index + 1,
+                id.unwrap_or_default()
+            );
+            let spec = spec.map_err(|e| anyhow!("{}: {}", at, e.trim_end()))?;
+            if spec.id.trim().is_empty() {
+                bail!("{}: id must not be empty", at);
+            }
+            if !seen.insert(spec.id.clone()) {
+                bail!("{}: duplicate rule id", at);
+            }
+            if spec.keywords.iter().any(|k| k.is_empty()) {
+                bail!("{}: keywords must not be empty strings", at);
+            }
+            let regex =
+                Regex::new(&spec.regex).with_context(|| format!("{}: invalid regex", at))?;
+            Ok(Rule {
+                id: spec.id,
+                description: spec.description,
+                regex,
+                secret_group: None,
+                entropy: None,
+                keywords: spec.keywords.iter().map(|k| k.to_lowercase()).collect(),
+                severity: spec.severity,
+                tags: spec.tags,
+                allowlists: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(result: Result<Vec<Rule>>) -> String {
+        match result {
+            Ok(_) => panic!("rules were accepted"),
+            Err(err) => format!("{:#}", err),
+        }
+    }
+
+    fn toml_error(contents: &str) -> String {
+        error(parse_toml("rules.toml", contents))
+    }
+
+    #[test]
+    fn valid_rules() {
+        let rules = parse_toml(
+            "rules.toml",
+            "[[rules]]\nid = 'a'\nregex = 'x'\nkeywords = ['Key']\nseverity = 'high'",
+        )
+        .unwrap();
+        assert_eq!(rules[0].keywords, ["key"]);
+        assert_eq!(rules[0].severity, Severity::High);
+        assert!(parse_toml("<bundled rules>", DEFAULT_RULES).is_ok());
+    }
+
+    #[test]
+    fn empty_id() {
+        let message =
+            toml_error("[[rules]]\nid = 'a'\nregex = 'x'\n[[rules]]\nid = ' '\nregex = 'x'");
+        assert!(message.starts_with("rules.toml: rule #2 (id \" \"): id must not be empty"));
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let message =
+            toml_error("[[rules]]\nid = 'a'\nregex = 'x'\n[[rules]]\nid = 'a'\nregex = 'y'");
+        assert!(message.starts_with("rules.toml: rule #2 (id \"a\"): duplicate rule id"));
+    }
+
+    #[test]
+    fn empty_keyword() {
+        let message = toml_error("[[rules]]\nid = 'a'\nregex = 'x'\nkeywords = ['k', '']");
+        assert!(message.starts_with("rules.toml: rule #1 (id \"a\"): keywords must not be empty"));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let message = toml_error("[[rules]]\nid = 'a'\nregex = '(x'");
+        assert!(message.starts_with("rules.toml: rule #1 (id \"a\"): invalid regex"));
+    }
+
+    #[test]
+    fn unknown_field() {
+        let message = toml_error("[[rules]]\nid = 'a'\nregex = 'x'\nsecretGroup = 1");
+        assert!(message.starts_with("rules.toml: rule #1 (id \"a\"): unknown field `secretGroup`"));
+        let message = error(parse_yaml(
+            "rules.yaml",
+            "rules:\n  - id: a\n    regex: x\n  - id: b\n    regex: y\n    color: red\n",
+        ));
+        assert!(message.starts_with("rules.yaml: rule #2 (id \"b\"): unknown field `color`"));
+    }
+
+    #[test]
+    fn missing_field() {
+        let message = toml_error("[[rules]]\nid = 'a'");
+        assert!(message.starts_with("rules.toml: rule #1 (id \"a\"): missing field `regex`"));
+    }
+
+    #[test]
+    fn bad_extension() {
+        let path = std::env::temp_dir().join(format!("evileye-rules-{}.json", std::process::id()));
+        std::fs::write(&path, "{}").unwrap();
+        let message = error(load_file(&path));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            message,
+            format!(
+                "{}: rule files must have a .toml, .yaml or .yml extension",
+                path.display()
+            )
+        );
+    }
+}